@@ -0,0 +1,100 @@
+//! Compare probing every corpus for the ngrams of a text with looking the
+//! ngrams up in the inverted index, both including the map of the text
+//! ngrams built for every scanned text.
+//!
+//! Run with `cargo bench`, the corpus is synthetic: every license has a
+//! signature of `SIGNATURE` ngrams and the text contains the signatures of
+//! a few of them among many unrelated ngrams.
+extern crate liceum;
+extern crate time;
+
+use std::collections::{HashMap, HashSet};
+
+use liceum::corpus::{self, InputVector, JsonInMap, NG, VecInData};
+use liceum::index::{Index, NGramMap};
+use liceum::ngram::NGram;
+
+/// Ngrams of every signature.
+const SIGNATURE: usize = 3;
+
+/// Ngrams of the scanned text.
+const TEXT_NGRAMS: usize = 5000;
+
+/// Texts scanned per measurement.
+const ROUNDS: usize = 50;
+
+/// Ngram of size 7 made from `seed`.
+fn ngram(seed: usize) -> NG {
+    let tokens: Vec<String> = (0..7).map(|i| format!("w{}", seed * 7 + i)).collect();
+    NGram::new(&tokens)
+}
+
+/// Corpus of `licenses` synthetic licenses.
+fn corpora(licenses: usize) -> InputVector {
+    let mut data: JsonInMap = HashMap::new();
+    for l in 0..licenses {
+        data.insert(format!("license-{}", l),
+                    VecInData {
+                        ngrams: (0..SIGNATURE).map(|i| ngram(l * SIGNATURE + i).hash).collect(),
+                        level: 1,
+                        excludes: Vec::new(),
+                        subset_of: Vec::new(),
+                        ssdeep_threshold: None,
+                        meta: None,
+                        vars: Vec::new(),
+                        offsets: Vec::new(),
+                        tokens: 0,
+                    });
+    }
+    corpus::load_data(&data)
+}
+
+/// Ngrams of a text containing signatures of every hundredth license.
+fn text(licenses: usize) -> Vec<NG> {
+    let unrelated = licenses * SIGNATURE;
+    let mut ngrams: Vec<NG> = (0..TEXT_NGRAMS).map(|i| ngram(unrelated + i)).collect();
+    for l in (0..licenses).filter(|x| x % 100 == 0) {
+        for i in 0..SIGNATURE {
+            ngrams.push(ngram(l * SIGNATURE + i));
+        }
+    }
+    ngrams
+}
+
+/// Licenses with signature ngrams in `ngrams`, probing every corpus.
+fn probe(corpora: &InputVector, ngrams: &[NG]) -> usize {
+    let distinct: HashSet<&NG> = ngrams.iter().collect();
+    corpora.iter()
+           .filter(|ic| ic.data.ngrams.iter().any(|x| distinct.contains(x)))
+           .count()
+}
+
+/// Licenses with signature ngrams in `ngrams`, looked up in `index`.
+fn lookup(index: &Index, ngrams: &[NG]) -> usize {
+    let distinct: NGramMap<()> = ngrams.iter().map(|x| (*x, ())).collect();
+    index.candidates(distinct.keys()).len()
+}
+
+/// Average time of `f` in microseconds.
+fn measure<F: FnMut() -> usize>(mut f: F) -> (f64, usize) {
+    let start = time::precise_time_ns();
+    let mut found = 0;
+    for _ in 0..ROUNDS {
+        found = f();
+    }
+    ((time::precise_time_ns() - start) as f64 / ROUNDS as f64 / 1000.0, found)
+}
+
+fn main() {
+    println!("{:>9} {:>12} {:>12} {:>8}", "licenses", "probe (us)", "index (us)", "found");
+    for &licenses in &[100, 1000, 10000] {
+        let corpora = corpora(licenses);
+        let index = Index::new(&corpora);
+        let ngrams = text(licenses);
+
+        let (probed, a) = measure(|| probe(&corpora, &ngrams));
+        let (indexed, b) = measure(|| lookup(&index, &ngrams));
+        assert_eq!(a, b);
+        println!("{:>9} {:>12.1} {:>12.1} {:>8}", licenses, probed, indexed, b);
+    }
+}
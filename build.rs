@@ -0,0 +1,130 @@
+//! Generate and embed the default corpus when building with the
+//! `embedded-corpus` feature.
+//!
+//! The corpus is generated from the license texts in `licenses` by the
+//! generator of the library, whose modules are compiled into the build
+//! script, so that it always follows the texts and the code. A data
+//! directory generated by `liceum -g`, e.g. of the whole SPDX license
+//! list, is embedded instead when `LICEUM_CORPUS` points to it.
+#![allow(dead_code)]
+extern crate regex;
+extern crate rustc_serialize;
+extern crate csv;
+extern crate walkdir;
+extern crate sha1;
+
+#[path = "src/corpus.rs"]
+mod corpus;
+#[path = "src/error.rs"]
+mod error;
+#[path = "src/ngram.rs"]
+mod ngram;
+#[path = "src/normalize.rs"]
+mod normalize;
+#[path = "src/spdx/mod.rs"]
+mod spdx;
+#[path = "src/ssdeep.rs"]
+mod ssdeep;
+#[path = "src/util.rs"]
+mod util;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use rustc_serialize::json;
+
+/// Sources of the library the generated corpus depends on.
+static GENERATOR: &'static [&'static str] = &["src/corpus.rs",
+                                              "src/error.rs",
+                                              "src/ngram.rs",
+                                              "src/normalize.rs",
+                                              "src/spdx",
+                                              "src/ssdeep.rs",
+                                              "src/util.rs"];
+
+/// Generate the corpus of the license texts in `licenses` into `out`.
+fn generate(licenses: &Path, out: &Path) {
+    let generated = match corpus::generate(&licenses.to_string_lossy(),
+                                           None,
+                                           &corpus::Header::default(),
+                                           false) {
+        Ok(x) => x,
+        Err(e) => panic!("Can't generate the corpus of {}: {}", licenses.display(), e),
+    };
+
+    fs::create_dir_all(out).unwrap();
+    util::write_file(&out.join("ngrams.json").to_string_lossy(),
+                     &corpus::to_json(&generated.data))
+        .unwrap();
+    util::write_file(&out.join("hashes.ssdeep").to_string_lossy(),
+                     &ssdeep::format_hashes(&generated.hashes))
+        .unwrap();
+    util::write_file(&out.join("texts.json").to_string_lossy(),
+                     &json::encode(&generated.texts).unwrap())
+        .unwrap();
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    if env::var_os("CARGO_FEATURE_EMBEDDED_CORPUS").is_none() {
+        return;
+    }
+    println!("cargo:rerun-if-env-changed=LICEUM_CORPUS");
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let (dir, external) = match env::var("LICEUM_CORPUS") {
+        Ok(x) => (PathBuf::from(x), true),
+        Err(_) => {
+            let root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+            let licenses = root.join("licenses");
+            println!("cargo:rerun-if-changed={}", licenses.display());
+            for source in GENERATOR {
+                println!("cargo:rerun-if-changed={}", root.join(source).display());
+            }
+            let dir = out_dir.join("corpus");
+            generate(&licenses, &dir);
+            (dir, false)
+        }
+    };
+    let binary = dir.join("ngrams.bin");
+    let ngrams = if binary.is_file() {
+        binary.clone()
+    } else {
+        dir.join("ngrams.json")
+    };
+    let hashes = dir.join("hashes.ssdeep");
+    let texts = dir.join("texts.json");
+
+    for file in &[&ngrams, &hashes] {
+        if !file.is_file() {
+            panic!("{} not found, point LICEUM_CORPUS to a data directory generated by \
+                    `liceum -g`",
+                   file.display());
+        }
+    }
+    for file in &[&ngrams, &hashes, &texts] {
+        if external && file.is_file() {
+            println!("cargo:rerun-if-changed={}", file.display());
+        }
+    }
+
+    let texts = if texts.is_file() {
+        format!("include_str!({:?})", texts.to_string_lossy())
+    } else {
+        String::from("\"\"")
+    };
+    let out = out_dir.join("embedded.rs");
+    let mut f = File::create(&out).unwrap();
+    write!(f,
+           "pub static NGRAMS: &'static [u8] = include_bytes!({:?});\n\
+            pub static BINARY: bool = {};\n\
+            pub static HASHES: &'static str = include_str!({:?});\n\
+            pub static TEXTS: &'static str = {};\n",
+           ngrams.to_string_lossy(),
+           ngrams == binary,
+           hashes.to_string_lossy(),
+           texts)
+        .unwrap();
+}
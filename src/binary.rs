@@ -0,0 +1,548 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::result;
+
+use memmap::{Mmap, Protection};
+use rustc_serialize::json;
+
+use corpus::{self, CorpusData, Header, InputCorpus, InputVector, JsonInMap, VecInData};
+use index::Index;
+use ngram::NGram;
+use error::{LiceumError, Result};
+use util::{read_file, write_file};
+use {BINARY_FILE, NGRAMS_FILE};
+
+/// Leading bytes of every binary corpus.
+pub const MAGIC: &'static [u8] = b"LICEUM\0\0";
+
+/// Version of the binary format, corpora of other versions are rejected.
+pub const FORMAT_VERSION: u32 = 1;
+
+// Magic, format version, reserved and the length of the metadata
+const PREAMBLE: usize = 24;
+
+/// Format of the ngram corpus in a data directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `NGRAMS_FILE`, pretty printed JSON
+    Json,
+    /// `BINARY_FILE`, see `encode`
+    Binary,
+}
+
+impl Format {
+    /// Parse format name, one of `json` or `binary`.
+    pub fn parse(spec: &str) -> result::Result<Format, String> {
+        match spec.trim() {
+            "json" => Ok(Format::Json),
+            "binary" => Ok(Format::Binary),
+            _ => Err(format!("Unknown corpus format: {}", spec)),
+        }
+    }
+
+    /// Name of the corpus file of this format inside a data directory.
+    pub fn file(&self) -> &'static str {
+        match *self {
+            Format::Json => NGRAMS_FILE,
+            Format::Binary => BINARY_FILE,
+        }
+    }
+}
+
+/// Range of ngrams in the tables, counted in ngrams.
+#[derive(RustcDecodable, RustcEncodable, Debug, Clone, Copy)]
+struct Table {
+    start: u64,
+    len: u64,
+}
+
+/// Corpus of a single license, its `data` is stored without the ngrams.
+#[derive(RustcDecodable, RustcEncodable, Debug, Clone)]
+struct Entry {
+    name: String,
+    data: VecInData,
+    ngrams: Table,
+    excludes: Table,
+}
+
+/// Everything stored in front of the ngram tables.
+#[derive(RustcDecodable, RustcEncodable, Debug, Clone)]
+struct Meta {
+    header: Header,
+    licenses: Vec<Entry>,
+    notices: Vec<Entry>,
+}
+
+fn put_u32(out: &mut Vec<u8>, x: u32) {
+    for i in 0..4 {
+        out.push((x >> (8 * i)) as u8);
+    }
+}
+
+fn put_u64(out: &mut Vec<u8>, x: u64) {
+    for i in 0..8 {
+        out.push((x >> (8 * i)) as u8);
+    }
+}
+
+fn get_u32(bytes: &[u8]) -> u32 {
+    bytes[..4].iter().rev().fold(0, |x, &b| x << 8 | b as u32)
+}
+
+fn get_u64(bytes: &[u8]) -> u64 {
+    bytes[..8].iter().rev().fold(0, |x, &b| x << 8 | b as u64)
+}
+
+/// Entries of `licenses` ordered by name, their ngrams are appended to
+/// `tables`.
+fn entries(licenses: &JsonInMap, tables: &mut Vec<u64>) -> Vec<Entry> {
+    let mut names: Vec<&String> = licenses.keys().collect();
+    names.sort();
+
+    let mut table = |ngrams: &[u64]| {
+        let t = Table {
+            start: tables.len() as u64,
+            len: ngrams.len() as u64,
+        };
+        tables.extend(ngrams.iter().cloned());
+        t
+    };
+    names.into_iter()
+         .map(|name| {
+             let mut data = licenses[name].clone();
+             let ngrams = table(&data.ngrams);
+             let excludes = table(&data.excludes);
+             data.ngrams.clear();
+             data.excludes.clear();
+             Entry {
+                 name: name.clone(),
+                 data: data,
+                 ngrams: ngrams,
+                 excludes: excludes,
+             }
+         })
+         .collect()
+}
+
+/// Encode `data` in the binary format: the preamble with `MAGIC`,
+/// `FORMAT_VERSION` and the length of the metadata, then the metadata (the
+/// header and all corpora without their ngrams) as JSON and finally the
+/// 8-byte aligned tables of ngram hashes. Numbers are little endian.
+pub fn encode(data: &CorpusData) -> Vec<u8> {
+    let mut tables: Vec<u64> = Vec::new();
+    let meta = Meta {
+        header: data.header.clone(),
+        licenses: entries(&data.licenses, &mut tables),
+        notices: entries(&data.notices, &mut tables),
+    };
+    let meta = json::encode(&meta).unwrap();
+
+    let mut out: Vec<u8> = Vec::with_capacity(PREAMBLE + meta.len() + 8 * (tables.len() + 1));
+    out.extend(MAGIC.iter().cloned());
+    put_u32(&mut out, FORMAT_VERSION);
+    put_u32(&mut out, 0);
+    put_u64(&mut out, meta.len() as u64);
+    out.extend(meta.bytes());
+    while out.len() % 8 != 0 {
+        out.push(0);
+    }
+    for x in tables {
+        put_u64(&mut out, x);
+    }
+    out
+}
+
+/// Write `data` in the binary format into `file`.
+pub fn write(data: &CorpusData, file: &Path) -> Result<()> {
+    let mut f = try!(File::create(file));
+    try!(f.write_all(&encode(data)));
+    Ok(())
+}
+
+/// Byte offset `x` counted from the start of the file, `None` if it
+/// doesn't fit into the address space.
+fn offset(x: u64) -> Option<usize> {
+    if x > usize::max_value() as u64 {
+        None
+    } else {
+        Some(x as usize)
+    }
+}
+
+/// Decode the metadata of the binary corpus `bytes` read from `name`,
+/// together with the byte offset of the tables.
+fn decode_meta(bytes: &[u8], name: &str) -> Result<(Meta, usize)> {
+    let truncated = || LiceumError::Mismatch(format!("{} is truncated", name));
+    if bytes.len() < PREAMBLE {
+        return Err(truncated());
+    }
+    if &bytes[..MAGIC.len()] != MAGIC {
+        return Err(LiceumError::Mismatch(format!("{} is not a binary corpus", name)));
+    }
+    let version = get_u32(&bytes[8..]);
+    if version != FORMAT_VERSION {
+        return Err(LiceumError::Mismatch(format!("binary corpus format version {} is not \
+                                                  supported, expected {}",
+                                                 version,
+                                                 FORMAT_VERSION)));
+    }
+
+    let end = match offset(get_u64(&bytes[16..])).and_then(|x| x.checked_add(PREAMBLE)) {
+        Some(x) if x <= bytes.len() => x,
+        _ => return Err(truncated()),
+    };
+    let meta = try!(String::from_utf8(bytes[PREAMBLE..end].to_vec()));
+    let meta: Meta = try!(json::decode(&meta));
+
+    // Every table has to lie within the file
+    let tables = (end + 7) / 8 * 8;
+    let available = bytes.len().saturating_sub(tables) / 8;
+    for e in meta.licenses.iter().chain(meta.notices.iter()) {
+        for t in &[e.ngrams, e.excludes] {
+            match t.start.checked_add(t.len).and_then(offset) {
+                Some(x) if x <= available => {}
+                _ => return Err(truncated()),
+            }
+        }
+    }
+    Ok((meta, tables))
+}
+
+/// Ngrams of table `t` of `bytes` whose tables start at byte `tables`,
+/// `t` has been checked by `decode_meta`.
+fn table(bytes: &[u8], tables: usize, t: Table) -> Vec<u64> {
+    let start = tables + 8 * t.start as usize;
+    (0..t.len as usize).map(|i| get_u64(&bytes[start + 8 * i..])).collect()
+}
+
+/// Corpora of `entries` with their ngrams read from the tables of `bytes`
+/// starting at byte `tables`.
+fn corpora(bytes: &[u8], tables: usize, entries: &[Entry]) -> JsonInMap {
+    entries.iter()
+           .map(|e| {
+               let mut data = e.data.clone();
+               data.ngrams = table(bytes, tables, e.ngrams);
+               data.excludes = table(bytes, tables, e.excludes);
+               (e.name.clone(), data)
+           })
+           .collect()
+}
+
+/// Decode the binary corpus `bytes`, see `encode`.
+pub fn decode(bytes: &[u8]) -> Result<CorpusData> {
+    let (meta, tables) = try!(decode_meta(bytes, "binary corpus"));
+    Ok(CorpusData {
+        licenses: corpora(bytes, tables, &meta.licenses),
+        notices: corpora(bytes, tables, &meta.notices),
+        header: meta.header,
+    })
+}
+
+/// Bytes of a binary corpus.
+enum Bytes {
+    Mapped(Mmap),
+    /// Corpus embedded into the binary
+    Static(&'static [u8]),
+}
+
+impl Bytes {
+    fn as_slice(&self) -> &[u8] {
+        match *self {
+            // The file isn't expected to change while it is loaded
+            Bytes::Mapped(ref map) => unsafe { map.as_slice() },
+            Bytes::Static(bytes) => bytes,
+        }
+    }
+}
+
+/// Binary corpus mapped into memory. Only the metadata is decoded when it
+/// is opened, the ngrams stay in the mapped tables and are indexed from
+/// there.
+pub struct BinaryCorpus {
+    bytes: Bytes,
+    meta: Meta,
+    // Byte offset of the tables
+    tables: usize,
+}
+
+impl BinaryCorpus {
+    /// Map `file` into memory and decode its metadata.
+    pub fn open(file: &Path) -> Result<BinaryCorpus> {
+        let f = try!(File::open(file));
+        if (try!(f.metadata()).len() as usize) < PREAMBLE {
+            return Err(LiceumError::Mismatch(format!("{} is truncated", file.display())));
+        }
+        let map = try!(Mmap::open(&f, Protection::Read));
+        BinaryCorpus::new(Bytes::Mapped(map), &file.display().to_string())
+    }
+
+    /// Binary corpus embedded into the binary as `bytes`.
+    pub fn from_static(bytes: &'static [u8]) -> Result<BinaryCorpus> {
+        BinaryCorpus::new(Bytes::Static(bytes), "embedded binary corpus")
+    }
+
+    fn new(bytes: Bytes, name: &str) -> Result<BinaryCorpus> {
+        let (meta, tables) = try!(decode_meta(bytes.as_slice(), name));
+        let corpus = BinaryCorpus {
+            bytes: bytes,
+            meta: meta,
+            tables: tables,
+        };
+        match corpus.meta.header.validate() {
+            Ok(()) => Ok(corpus),
+            Err(e) => Err(LiceumError::Mismatch(e.to_string())),
+        }
+    }
+
+    /// Parameters the corpus was generated with.
+    pub fn header(&self) -> &Header {
+        &self.meta.header
+    }
+
+    /// Number of license and notice corpora.
+    pub fn len(&self) -> usize {
+        self.meta.licenses.len() + self.meta.notices.len()
+    }
+
+    /// Predicate determining whether the corpus holds no licenses.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// License corpora without their ngrams, ordered by level and name.
+    pub fn licenses(&self) -> InputVector {
+        BinaryCorpus::input(&self.meta.licenses)
+    }
+
+    /// Notice corpora without their ngrams, ordered by level and name.
+    pub fn notices(&self) -> InputVector {
+        BinaryCorpus::input(&self.meta.notices)
+    }
+
+    /// Index of the ngrams of `licenses` read from the tables.
+    pub fn license_index(&self) -> Index {
+        self.index(&self.meta.licenses)
+    }
+
+    /// Index of the ngrams of `notices` read from the tables.
+    pub fn notice_index(&self) -> Index {
+        self.index(&self.meta.notices)
+    }
+
+    /// `entries` in the order of the `InputVector`, see
+    /// `corpus::load_data`.
+    fn ordered(entries: &[Entry]) -> Vec<&Entry> {
+        let mut ordered: Vec<&Entry> = entries.iter().collect();
+        ordered.sort_by_key(|x| x.data.level);
+        ordered
+    }
+
+    fn input(entries: &[Entry]) -> InputVector {
+        BinaryCorpus::ordered(entries)
+            .into_iter()
+            .map(|e| {
+                InputCorpus {
+                    file: e.name.clone(),
+                    data: corpus::in_data(&e.data),
+                }
+            })
+            .collect()
+    }
+
+    fn index(&self, entries: &[Entry]) -> Index {
+        let bytes = self.bytes.as_slice();
+        let ngrams = |t: Table| {
+            let start = self.tables + 8 * t.start as usize;
+            (0..t.len as usize).map(move |i| NGram::from_hash(get_u64(&bytes[start + 8 * i..])))
+        };
+        let mut index = Index::default();
+        for e in BinaryCorpus::ordered(entries) {
+            index.push(ngrams(e.ngrams), ngrams(e.excludes));
+        }
+        index
+    }
+
+    /// All corpora in their primitive representation.
+    pub fn corpus_data(&self) -> CorpusData {
+        let bytes = self.bytes.as_slice();
+        CorpusData {
+            header: self.meta.header.clone(),
+            licenses: corpora(bytes, self.tables, &self.meta.licenses),
+            notices: corpora(bytes, self.tables, &self.meta.notices),
+        }
+    }
+}
+
+/// Read the ngram corpus of `data_dir`, `BINARY_FILE` when present and
+/// `NGRAMS_FILE` otherwise.
+pub fn read_data(data_dir: &str) -> Result<CorpusData> {
+    let binary = Path::new(data_dir).join(BINARY_FILE);
+    let data = if binary.is_file() {
+        try!(BinaryCorpus::open(&binary)).corpus_data()
+    } else {
+        let d = try!(read_file(&Path::new(data_dir).join(NGRAMS_FILE).to_string_lossy()));
+        try!(json::decode(&d))
+    };
+    try!(corpus::validate(&data));
+    Ok(data)
+}
+
+/// Convert the ngram corpus of `data_dir` into `format`. The file of the
+/// other format is removed, a binary corpus would be loaded in place of the
+/// JSON one and a JSON corpus left next to a binary one would go stale.
+pub fn convert(data_dir: &str, format: Format) -> Result<()> {
+    let data = try!(read_data(data_dir));
+    let target = Path::new(data_dir).join(format.file());
+    match format {
+        Format::Json => {
+            try!(write_file(&target.to_string_lossy(), &corpus::to_json(&data)));
+        }
+        Format::Binary => try!(write(&data, &target)),
+    }
+
+    let other = match format {
+        Format::Json => Format::Binary,
+        Format::Binary => Format::Json,
+    };
+    let other = Path::new(data_dir).join(other.file());
+    if other.is_file() {
+        try!(fs::remove_file(other));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    use corpus::{self, CorpusData, Header, LicenseSource};
+    use error::LiceumError;
+    use index::{Posting, Role};
+
+    fn source(name: &str, prefix: &str, n: usize) -> LicenseSource {
+        LicenseSource {
+            name: String::from(name),
+            text: (0..n).map(|i| format!("{}{}", prefix, i)).collect::<Vec<String>>().join(" "),
+            template: None,
+            meta: None,
+        }
+    }
+
+    fn data() -> CorpusData {
+        let sources = [source("A", "alpha", 60), source("B", "beta", 40)];
+        corpus::generate_corpuses(&sources, &Header::default(), false).unwrap()
+    }
+
+    fn rejected(bytes: &[u8]) -> bool {
+        match decode(bytes) {
+            Err(LiceumError::Mismatch(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Binary corpus of `meta` followed by `tables`.
+    fn assemble(meta: &str, tables: &[u8]) -> Vec<u8> {
+        let mut out: Vec<u8> = MAGIC.to_vec();
+        put_u32(&mut out, FORMAT_VERSION);
+        put_u32(&mut out, 0);
+        put_u64(&mut out, meta.len() as u64);
+        out.extend(meta.bytes());
+        while out.len() % 8 != 0 {
+            out.push(0);
+        }
+        out.extend(tables.iter().cloned());
+        out
+    }
+
+    /// Metadata and tables of `bytes`.
+    fn split(bytes: &[u8]) -> (String, Vec<u8>) {
+        let end = PREAMBLE + get_u64(&bytes[16..]) as usize;
+        let meta = String::from_utf8(bytes[PREAMBLE..end].to_vec()).unwrap();
+        (meta, bytes[(end + 7) / 8 * 8..].to_vec())
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = data();
+        let decoded = decode(&encode(&data)).unwrap();
+        assert_eq!(decoded.header.ngram_size, data.header.ngram_size);
+        for &(a, b) in &[(&data.licenses, &decoded.licenses), (&data.notices, &decoded.notices)] {
+            assert_eq!(a.len(), b.len());
+            for (name, x) in a {
+                let y = &b[name];
+                assert_eq!((x.level, &x.ngrams, &x.excludes), (y.level, &y.ngrams, &y.excludes));
+                assert_eq!((&x.offsets, x.tokens), (&y.offsets, y.tokens));
+            }
+        }
+    }
+
+    #[test]
+    fn index_from_tables() {
+        let data = data();
+        let file = env::temp_dir().join(format!("liceum-test-{}.bin", process::id()));
+        write(&data, &file).unwrap();
+        let mapped = BinaryCorpus::open(&file);
+        fs::remove_file(&file).unwrap();
+        let mapped = mapped.unwrap();
+
+        let licenses = mapped.licenses();
+        let index = mapped.license_index();
+        assert_eq!(licenses.len(), data.licenses.len());
+        assert!(licenses.iter().all(|x| x.data.ngrams.is_empty()));
+        for ic in corpus::load_data(&data.licenses) {
+            let c = licenses.iter().position(|x| x.file == ic.file).unwrap();
+            for (i, g) in ic.data.ngrams.iter().enumerate() {
+                let p = Posting {
+                    corpus: c,
+                    role: Role::Signature(i),
+                };
+                assert!(index.lookup(g).contains(&p));
+            }
+        }
+        assert_eq!(index.len(), Index::new(&corpus::load_data(&data.licenses)).len());
+    }
+
+    #[test]
+    fn reject_truncated() {
+        let bytes = encode(&data());
+        for &len in &[0, 10, PREAMBLE, PREAMBLE + 5, bytes.len() - 8] {
+            assert!(rejected(&bytes[..len]), "accepted {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn reject_foreign() {
+        let bytes = encode(&data());
+
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        assert!(rejected(&magic));
+
+        let mut version = bytes.clone();
+        version[8] = FORMAT_VERSION as u8 + 1;
+        assert!(rejected(&version));
+    }
+
+    #[test]
+    fn reject_overflowing_lengths() {
+        let bytes = encode(&data());
+
+        let mut meta_len = bytes.clone();
+        for b in &mut meta_len[16..24] {
+            *b = 0xff;
+        }
+        assert!(rejected(&meta_len));
+
+        let (meta, tables) = split(&bytes);
+        assert!(!rejected(&assemble(&meta, &tables)));
+        let start = meta.replacen("\"start\":0", &format!("\"start\":{}", u64::max_value()), 1);
+        assert!(rejected(&assemble(&start, &tables)));
+        let at = meta.find("\"len\":").unwrap() + 6;
+        let digits = meta[at..].find(|c: char| !c.is_digit(10)).unwrap();
+        let len = format!("{}{}{}", &meta[..at], u64::max_value() / 8, &meta[at + digits..]);
+        assert!(rejected(&assemble(&len, &tables)));
+    }
+}
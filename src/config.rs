@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use rustc_serialize::json;
+
+use detector::Detector;
+use error::{LiceumError, Result};
+use fusion::Fusion;
+use policy::Policy;
+use util::read_file;
+
+/// Detector settings read from a JSON configuration file, all keys are
+/// optional:
+///
+/// ```json
+/// {
+///    "ssdeep_threshold": 80,
+///    "min_confidence": 0.6,
+///    "fusion": "prefer-ngram",
+///    "license_thresholds": { "MIT": 60 },
+///    "validate_vars": true,
+///    "detect_notices": true,
+///    "segment": false,
+///    "explain": false,
+///    "allowed_licenses": ["MIT", "Apache-2.0"],
+///    "denied_licenses": ["GPL-3.0"]
+/// }
+/// ```
+#[derive(RustcDecodable, Debug, Clone, Default)]
+pub struct Config {
+    /// Minimal ssdeep similarity of reported licenses
+    pub ssdeep_threshold: Option<u32>,
+    /// Minimal confidence of reported ngram matches
+    pub min_confidence: Option<f64>,
+    /// Name of the fusion policy
+    pub fusion: Option<String>,
+    /// Per-license ssdeep thresholds, stored into the corpus on generation
+    pub license_thresholds: Option<HashMap<String, u32>>,
+    /// Validate ngram matches against SPDX template variables
+    pub validate_vars: Option<bool>,
+    /// Search for license notices besides full license texts
+    pub detect_notices: Option<bool>,
+    /// Split files into segments of single licenses
+    pub segment: Option<bool>,
+    /// Explain differences from the texts of the licenses found
+    pub explain: Option<bool>,
+    /// If present only these licenses are acceptable
+    pub allowed_licenses: Option<Vec<String>>,
+    /// Licenses which are never acceptable
+    pub denied_licenses: Option<Vec<String>>,
+}
+
+impl Config {
+    /// Read configuration from JSON `file`.
+    pub fn load(file: &str) -> Result<Config> {
+        let d = try!(read_file(file));
+        json::decode(&d).map_err(|e| {
+            LiceumError::Usage(format!("Invalid config file {}: {}", file, e))
+        })
+    }
+
+    /// Apply all settings present in the configuration to `detector`.
+    pub fn apply(&self, detector: &mut Detector) -> Result<()> {
+        if let Some(x) = self.ssdeep_threshold {
+            try!(detector.set_ssdeep_threshold(x));
+        }
+        if let Some(x) = self.min_confidence {
+            detector.set_min_confidence(x);
+        }
+        if let Some(ref x) = self.fusion {
+            detector.set_fusion(try!(Fusion::parse(x).map_err(LiceumError::Usage)));
+        }
+        if let Some(ref thresholds) = self.license_thresholds {
+            for (license, threshold) in thresholds {
+                try!(detector.set_license_threshold(license, *threshold));
+            }
+        }
+        if let Some(x) = self.validate_vars {
+            detector.set_validate_vars(x);
+        }
+        if let Some(x) = self.detect_notices {
+            detector.set_detect_notices(x);
+        }
+        if let Some(x) = self.segment {
+            detector.set_segment(x);
+        }
+        if let Some(x) = self.explain {
+            detector.set_explain(x);
+        }
+
+        Ok(())
+    }
+
+    /// License policy described by the configuration.
+    pub fn policy(&self) -> Policy {
+        Policy {
+            allowed: self.allowed_licenses.clone().unwrap_or_else(Vec::new),
+            denied: self.denied_licenses.clone().unwrap_or_else(Vec::new),
+        }
+    }
+}
@@ -0,0 +1,807 @@
+use std;
+use std::io::Write;
+use std::collections::{HashSet, HashMap};
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::{fs, fmt};
+use std::path::Path;
+
+use rustc_serialize::json;
+
+use ngram::{self, NGram};
+use normalize::{Normalization, Token};
+use spdx::list::{self, LicenseMeta};
+use spdx::template::{self, Template, VarContext};
+use ssdeep;
+use error::{LiceumError, Result};
+use util::{OneToMany, read_file};
+
+/// Default number of tokens per ngram
+pub const NGRAM_SIZE: usize = 7;
+
+/// Default number of unique ngrams after which a corpus is considered finished
+pub const UNIQUE_NGRAMS: usize = 3;
+
+/// Generic container for `leveled ngrams`
+#[derive(RustcDecodable, RustcEncodable, Debug, Clone)]
+pub struct Data<T> {
+    pub ngrams: Vec<T>,
+    pub level: u64,
+    /// Ngrams whose presence rules the corpus out, they come from larger
+    /// texts containing the whole corpus
+    pub excludes: Vec<T>,
+    /// Corpora whose text contains all ngrams of this one
+    pub subset_of: Vec<String>,
+    /// Minimal ssdeep similarity overriding the detector-wide threshold
+    pub ssdeep_threshold: Option<u32>,
+    /// SPDX metadata of licenses generated from the SPDX license list
+    pub meta: Option<LicenseMeta>,
+    /// Template variables used to validate matches
+    pub vars: Vec<VarContext>,
+    /// Token offset of every signature ngram in the license text
+    pub offsets: Vec<usize>,
+    /// Number of tokens of the license text
+    pub tokens: usize,
+}
+
+impl<T> Data<T> {
+    fn new(level: u64) -> Data<T> {
+        Data {
+            ngrams: Vec::new(),
+            level: level,
+            excludes: Vec::new(),
+            subset_of: Vec::new(),
+            ssdeep_threshold: None,
+            meta: None,
+            vars: Vec::new(),
+            offsets: Vec::new(),
+            tokens: 0,
+        }
+    }
+}
+
+/// Hashed ngram of normalized tokens
+pub type NG = NGram;
+
+/// Generic vector owning it's ngrams
+pub type NGramVec = Vec<NG>;
+
+/// Raw output and input data for processing
+type OutData<'a> = Data<&'a NG>;
+pub type InData = Data<NG>;
+
+/// Primitive representation of the JSON document, ngrams are stored as
+/// their hashes
+pub type VecInData = Data<u64>;
+pub type JsonInMap = HashMap<String, VecInData>;
+
+/// Parameters the corpus was generated with, stored as the header of
+/// `NGRAMS_FILE` so that search uses the same ones.
+#[derive(RustcDecodable, RustcEncodable, Debug, Clone)]
+pub struct Header {
+    pub normalization: Normalization,
+    /// Number of tokens per ngram
+    pub ngram_size: usize,
+    /// Number of unique ngrams after which a corpus is considered finished
+    pub unique_ngrams: usize,
+}
+
+impl Default for Header {
+    fn default() -> Header {
+        Header {
+            normalization: Normalization::default(),
+            ngram_size: NGRAM_SIZE,
+            unique_ngrams: UNIQUE_NGRAMS,
+        }
+    }
+}
+
+impl Header {
+    /// Check that the parameters are usable for generation and search.
+    pub fn validate(&self) -> Result<()> {
+        if self.ngram_size == 0 {
+            return Err(LiceumError::Usage(String::from("Ngram size must be at least 1")));
+        }
+        if self.unique_ngrams == 0 {
+            return Err(LiceumError::Usage(String::from("Number of unique ngrams must be at \
+                                                        least 1")));
+        }
+        Ok(())
+    }
+}
+
+/// Primitive representation of the whole `NGRAMS_FILE` document
+#[derive(RustcDecodable, RustcEncodable, Debug, Clone)]
+pub struct CorpusData {
+    pub header: Header,
+    pub licenses: JsonInMap,
+    /// Corpora of license notices, the short headers referring to a
+    /// license instead of its full text
+    pub notices: JsonInMap,
+}
+
+/// License text kept in the data directory to explain matches.
+#[derive(RustcDecodable, RustcEncodable, Debug, Clone)]
+pub struct LicenseText {
+    pub text: String,
+    /// SPDX license template of the text
+    pub template: Option<String>,
+    /// Ngrams of the normalized text in order, so that combined corpora
+    /// are told apart without normalizing the texts again. Data
+    /// directories generated before don't have them.
+    pub ngrams: Option<Vec<u64>>,
+}
+
+/// Primitive representation of the whole `TEXTS_FILE` document
+#[derive(RustcDecodable, RustcEncodable, Debug, Clone, Default)]
+pub struct TextsData {
+    pub licenses: HashMap<String, LicenseText>,
+    pub notices: HashMap<String, LicenseText>,
+}
+
+/// Input corpus structure holding basic information
+pub struct InputCorpus {
+    pub file: String,
+    pub data: InData,
+}
+
+/// All input corpuses
+pub type InputVector = Vec<InputCorpus>;
+
+/// License text corpora are generated from.
+#[derive(Debug, Clone)]
+pub struct LicenseSource {
+    /// Name of the corpus, SPDX identifier for the SPDX license list
+    pub name: String,
+    pub text: String,
+    /// SPDX license template with the markup of replaceable and optional
+    /// parts of the text
+    pub template: Option<String>,
+    pub meta: Option<LicenseMeta>,
+}
+
+/// Maps corpus name to vector of ngrams.
+struct LicenseCorpus {
+    name: String,
+    ngrams: NGramVec,
+    /// Number of tokens of the text
+    tokens: usize,
+    /// Ngrams of the required text of the license template, only these
+    /// may become signature ngrams
+    fixed: Option<HashSet<NG>>,
+}
+
+impl LicenseCorpus {
+    /// Predicate determining whether `ngram` may be part of the signature,
+    /// ngrams overlapping variable or optional text may not.
+    fn is_stable(&self, ngram: &NG) -> bool {
+        self.fixed.as_ref().map_or(true, |x| x.contains(ngram))
+    }
+}
+
+impl PartialEq for LicenseCorpus {
+    fn eq(&self, other: &LicenseCorpus) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for LicenseCorpus {}
+
+impl Hash for LicenseCorpus {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state)
+    }
+}
+
+impl Debug for LicenseCorpus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "LicenseCorpus: {} ({} ngrams)",
+               self.name,
+               self.ngrams.len())
+    }
+}
+
+/// Get ngrams of size `n` from input string `from` normalized according
+/// to `norm`.
+pub fn get_ngrams(from: &str, n: usize, norm: &Normalization) -> NGramVec {
+    get_spanned_ngrams(from, n, norm).into_iter().map(|x| x.0).collect()
+}
+
+/// Get ngrams of size `n` from input string `from` normalized according
+/// to `norm` together with the byte range of `from` each of them spans.
+pub fn get_spanned_ngrams(from: &str, n: usize, norm: &Normalization) -> Vec<(NG, usize, usize)> {
+    token_ngrams(&norm.spanned_tokens(from), n)
+}
+
+/// Get ngrams of size `n` from `tokens` together with the byte range of
+/// the original text each of them spans, ngram `i` starts at token `i`.
+pub fn token_ngrams(tokens: &[Token], n: usize) -> Vec<(NG, usize, usize)> {
+    if n == 0 {
+        return Vec::new();
+    }
+
+    // Every window of `n` consecutive tokens is an ngram:
+    //
+    // I.   | ABCDEFG
+    // II.  |  BCDEFGH
+    // III. |   CDEFGHI
+    //  ...
+    let hashes: Vec<u64> = tokens.iter().map(|x| ngram::hash_token(&x.text)).collect();
+    NGram::windows(&hashes, n)
+        .into_iter()
+        .zip(tokens.windows(n))
+        .map(|(g, w)| (g, w[0].start, w[n - 1].end))
+        .collect()
+}
+
+/// Name of the corpus stored in `file`, that is the file name
+/// without the extension, `None` when `file` has no file name.
+pub fn corpus_name(file: &str) -> Option<String> {
+    Path::new(file).file_stem().map(|x| x.to_string_lossy().into_owned())
+}
+
+/// Save data from the input map into a `JsonInMap` for JSON serialization.
+fn save_data(data: &HashMap<&LicenseCorpus, OutData>) -> JsonInMap {
+    let mut out: JsonInMap = HashMap::new();
+
+    for (corpus, ngrams) in data {
+        let data = VecInData {
+            level: ngrams.level,
+            ngrams: ngrams.ngrams.iter().map(|g| g.hash).collect(),
+            excludes: ngrams.excludes.iter().map(|g| g.hash).collect(),
+            subset_of: ngrams.subset_of.clone(),
+            ssdeep_threshold: ngrams.ssdeep_threshold,
+            meta: None,
+            vars: Vec::new(),
+            offsets: Vec::new(),
+            tokens: corpus.tokens,
+        };
+
+        out.insert(corpus.name.clone(), data);
+    }
+
+    out
+}
+
+/// Convert loaded `licenses` back into their primitive representation.
+pub fn store_data(licenses: &InputVector) -> JsonInMap {
+    let mut out: JsonInMap = HashMap::new();
+
+    for license in licenses {
+        let data = VecInData {
+            level: license.data.level,
+            ngrams: license.data.ngrams.iter().map(|g| g.hash).collect(),
+            excludes: license.data.excludes.iter().map(|g| g.hash).collect(),
+            subset_of: license.data.subset_of.clone(),
+            ssdeep_threshold: license.data.ssdeep_threshold,
+            meta: license.data.meta.clone(),
+            vars: license.data.vars.clone(),
+            offsets: license.data.offsets.clone(),
+            tokens: license.data.tokens,
+        };
+
+        out.insert(license.file.clone(), data);
+    }
+
+    out
+}
+
+/// Input representation of the primitive corpus data `v`.
+pub fn in_data(v: &VecInData) -> InData {
+    InData {
+        level: v.level,
+        ngrams: v.ngrams.iter().map(|x| NGram::from_hash(*x)).collect(),
+        excludes: v.excludes.iter().map(|x| NGram::from_hash(*x)).collect(),
+        subset_of: v.subset_of.clone(),
+        ssdeep_threshold: v.ssdeep_threshold,
+        meta: v.meta.clone(),
+        vars: v.vars.clone(),
+        offsets: v.offsets.clone(),
+        tokens: v.tokens,
+    }
+}
+
+/// Creates `InputVector` sorted by data level from
+/// input license corpus.
+pub fn load_data(input: &JsonInMap) -> InputVector {
+    let mut licenses: InputVector = Vec::new();
+
+    for (k, v) in input {
+        let item = InputCorpus {
+            file: k.clone(),
+            data: in_data(v),
+        };
+
+        licenses.push(item);
+    }
+
+    licenses.sort_by_key(|x| x.data.level);
+
+    licenses
+}
+
+/// Check that the header of `data` is usable for search, ngrams are
+/// stored as hashes so their size is known from the header alone.
+pub fn validate(data: &CorpusData) -> Result<()> {
+    match data.header.validate() {
+        Ok(()) => Ok(()),
+        Err(e) => Err(LiceumError::Mismatch(e.to_string())),
+    }
+}
+
+/// Format `data` as pretty JSON suitable for writing into `NGRAMS_FILE`.
+pub fn to_json(data: &CorpusData) -> String {
+    let pj = json::as_pretty_json(data).indent(3);
+    format!("{}", &pj)
+}
+
+/// Distinct stable ngrams of `corpus` ordered by the number of corpora
+/// they appear in.
+fn rarest_ngrams<'a>(corpus: &'a LicenseCorpus,
+                     ngrammap: &HashMap<&'a NG, Vec<&'a LicenseCorpus>>)
+                     -> Vec<&'a NG> {
+    let mut seen: HashSet<&NG> = HashSet::new();
+    let mut ngrams: Vec<&NG> = corpus.ngrams
+                                     .iter()
+                                     .filter(|x| corpus.is_stable(x) && seen.insert(x))
+                                     .collect();
+    ngrams.sort_by_key(|x| ngrammap.get(x).map_or(0, |c| c.len()));
+    ngrams
+}
+
+/// Make sure the signature of every corpus in `fm` tells it apart from
+/// all other corpora.
+///
+/// Signatures made of shared ngrams (higher levels, corpora finished
+/// without unique ngrams) may be fully contained in other texts. For every
+/// such text an ngram missing from it is added to the signature, if there
+/// is none the text contains the whole corpus and ngrams present only in
+/// the larger text are recorded as `excludes` instead.
+fn discriminate<'a>(corpuses: &'a [LicenseCorpus],
+                    ngrammap: &HashMap<&'a NG, Vec<&'a LicenseCorpus>>,
+                    unique: usize,
+                    fm: &mut HashMap<&'a LicenseCorpus, OutData<'a>>) {
+    let sets: HashMap<&LicenseCorpus, HashSet<&NG>> = corpuses.iter()
+                                                             .map(|c| {
+                                                                 (c, c.ngrams.iter().collect())
+                                                             })
+                                                             .collect();
+
+    for corpus in corpuses {
+        let own = &sets[corpus];
+        let data = match fm.get_mut(corpus) {
+            Some(x) => x,
+            None => continue,
+        };
+        if own.is_empty() {
+            continue;
+        }
+
+        let rarest = rarest_ngrams(corpus, ngrammap);
+        let mut shadow: Vec<&LicenseCorpus>;
+        loop {
+            // Other corpora matching the whole signature
+            shadow = corpuses.iter()
+                             .filter(|c| {
+                                 *c != corpus && data.ngrams.iter().all(|g| sets[c].contains(g))
+                             })
+                             .collect();
+
+            // Discriminate by presence of an ngram the other text lacks
+            let missing = shadow.iter()
+                                .filter_map(|c| rarest.iter().find(|g| !sets[c].contains(*g)))
+                                .next();
+            match missing {
+                Some(g) => data.ngrams.push(*g),
+                None => break,
+            }
+        }
+
+        // Remaining corpora contain the whole text, discriminate by absence
+        for superset in shadow {
+            data.subset_of.push(superset.name.clone());
+            for g in rarest_ngrams(superset, ngrammap)
+                         .into_iter()
+                         .filter(|g| !own.contains(g))
+                         .take(unique) {
+                data.excludes.push(g);
+            }
+        }
+        data.subset_of.sort();
+    }
+}
+
+/// Read license texts from `data_dir`, either a checkout of the SPDX
+/// license-list-data repository or a directory with one license text per
+/// file named after the license.
+pub fn read_sources(data_dir: &str) -> Result<Vec<LicenseSource>> {
+    if let Some(sources) = try!(list::read(data_dir)) {
+        return Ok(sources);
+    }
+    read_dir_sources(data_dir)
+}
+
+/// Read license notices from `dir` with one notice per file named after
+/// the license.
+pub fn read_notices(dir: &str) -> Result<Vec<LicenseSource>> {
+    read_dir_sources(dir)
+}
+
+/// Notices the SPDX license list recommends for licenses in `sources`.
+pub fn standard_notices(sources: &[LicenseSource]) -> Vec<LicenseSource> {
+    sources.iter()
+           .filter_map(|s| {
+               s.meta.as_ref().and_then(|m| m.standard_header.as_ref()).map(|header| {
+                   let (text, template) = template::split(header.clone());
+                   LicenseSource {
+                       name: s.name.clone(),
+                       text: text,
+                       template: template,
+                       meta: None,
+                   }
+               })
+           })
+           .collect()
+}
+
+/// Texts of `sources` keyed by the corpus name together with their
+/// ngrams according to `header`.
+pub fn texts(sources: &[LicenseSource], header: &Header) -> HashMap<String, LicenseText> {
+    sources.iter()
+           .map(|s| {
+               let ngrams = get_ngrams(&s.text, header.ngram_size, &header.normalization);
+               (s.name.clone(),
+                LicenseText {
+                   text: s.text.clone(),
+                   template: s.template.clone(),
+                   ngrams: Some(ngrams.into_iter().map(|x| x.hash).collect()),
+               })
+           })
+           .collect()
+}
+
+/// Normalized tokens of `license` together with the token ranges taken by
+/// the variables and optional parts of its template, empty variables give
+/// empty ranges at their position.
+pub fn reference_tokens(license: &LicenseText,
+                        norm: &Normalization)
+                        -> (Vec<String>, Vec<(usize, usize)>) {
+    let template = license.template.as_ref().and_then(|x| Template::parse(x).ok());
+    let (text, spans) = match template {
+        Some(t) => (t.original(), t.variable_spans()),
+        None => (license.text.clone(), Vec::new()),
+    };
+
+    let tokens = norm.spanned_tokens(&text);
+    let ranges = spans.iter()
+                      .map(|&(start, end)| {
+                          let first = tokens.iter().take_while(|x| x.end <= start).count();
+                          let last = tokens.iter().take_while(|x| x.start < end).count();
+                          (first, std::cmp::max(first, last))
+                      })
+                      .collect();
+    (tokens.into_iter().map(|x| x.text).collect(), ranges)
+}
+
+/// Read texts from `dir` with one text per file named after the license.
+fn read_dir_sources(dir: &str) -> Result<Vec<LicenseSource>> {
+    let mut sources: Vec<LicenseSource> = Vec::new();
+    let paths = try!(fs::read_dir(dir));
+    for path in paths {
+        let p = try!(path).path();
+        if !p.is_file() {
+            continue;
+        }
+
+        let file = match p.to_str() {
+            Some(x) => String::from(x),
+            None => return Err(LiceumError::Encoding(format!("{:?}", p))),
+        };
+        // Texts with SPDX template markup, as in the `template` directory
+        // of the SPDX license-list-data
+        let (text, template) = template::split(try!(read_file(&file)));
+        let name = match corpus_name(&file) {
+            Some(x) => x,
+            None => continue,
+        };
+        sources.push(LicenseSource {
+            name: String::from(name.trim_right_matches(template::TEMPLATE_SUFFIX)),
+            text: text,
+            template: template,
+            meta: None,
+        });
+    }
+
+    Ok(sources)
+}
+
+/// Ngrams of the required text of `template`, ngrams spanning variable or
+/// optional text don't appear in every instance of the license.
+pub fn fixed_ngrams(template: &Template, header: &Header) -> HashSet<NG> {
+    template.fixed_segments()
+            .iter()
+            .flat_map(|x| get_ngrams(x, header.ngram_size, &header.normalization))
+            .collect()
+}
+
+/// Contents of a data directory generated from license texts.
+pub struct Generated {
+    pub data: CorpusData,
+    /// ssdeep hashes of the license texts as `hash, name` pairs
+    pub hashes: Vec<(String, String)>,
+    pub texts: TextsData,
+}
+
+/// Generate the corpus of license texts in `data_dir`, see
+/// `read_sources`, using generation parameters from `header`. Notices are
+/// generated from the standard headers of the SPDX license list and from
+/// `notices_dir`, whose notices take precedence.
+pub fn generate(data_dir: &str,
+                notices_dir: Option<&str>,
+                header: &Header,
+                verbose: bool)
+                -> Result<Generated> {
+    let sources = try!(read_sources(data_dir));
+    let mut data = try!(generate_corpuses(&sources, header, verbose));
+
+    let mut notices = standard_notices(&sources);
+    if let Some(dir) = notices_dir {
+        let own = try!(read_notices(dir));
+        notices.retain(|x| !own.iter().any(|n| n.name == x.name));
+        notices.extend(own);
+    }
+    if !notices.is_empty() {
+        data.notices = try!(generate_corpuses(&notices, header, verbose)).licenses;
+    }
+
+    Ok(Generated {
+        data: data,
+        hashes: sources.iter()
+                       .map(|x| (ssdeep::hash_bytes(x.text.as_bytes()), x.name.clone()))
+                       .collect(),
+        texts: TextsData {
+            licenses: texts(&sources, header),
+            notices: texts(&notices, header),
+        },
+    })
+}
+
+/// Generate ngram corpuses from license texts in `sources` using
+/// parameters from `header` and return them in their primitive
+/// representation.
+pub fn generate_corpuses(sources: &[LicenseSource],
+                         header: &Header,
+                         verbose: bool)
+                         -> Result<CorpusData> {
+    try!(header.validate());
+
+    let mut corpuses: Vec<LicenseCorpus> = Vec::new();
+    let mut vars: HashMap<String, Vec<VarContext>> = HashMap::new();
+    for source in sources {
+        if verbose {
+            writeln!(&mut std::io::stderr(), "{}", source.name).ok();
+        }
+
+        let template = match source.template.as_ref().map(|x| Template::parse(x)) {
+            Some(Ok(t)) => Some(t),
+            Some(Err(e)) => {
+                if verbose {
+                    writeln!(&mut std::io::stderr(),
+                             "[!] Ignoring template of {}: {}",
+                             source.name,
+                             e)
+                        .ok();
+                }
+                None
+            }
+            None => None,
+        };
+
+        let fixed = template.as_ref().map(|t| fixed_ngrams(t, header));
+        let tokens = header.normalization.spanned_tokens(&source.text);
+        corpuses.push(LicenseCorpus {
+            name: source.name.clone(),
+            ngrams: token_ngrams(&tokens, header.ngram_size).into_iter().map(|x| x.0).collect(),
+            tokens: tokens.len(),
+            fixed: fixed,
+        });
+        if let Some(t) = template {
+            vars.insert(source.name.clone(), t.var_contexts());
+        }
+    }
+
+    let mut ngrammap: HashMap<&NG, Vec<&LicenseCorpus>> = HashMap::new();
+    if verbose {
+        writeln!(&mut std::io::stderr(),
+                 "[+] Generating n-gram map for {} corpuses",
+                 corpuses.len())
+            .ok();
+    }
+
+    {
+        let z = &corpuses;
+        for corpus in z {
+            for ngram in &corpus.ngrams {
+                // ngrams repeated within a single text count as one edge
+                let repeated = ngrammap.get(ngram).map_or(false, |x| x.last() == Some(&corpus));
+                if !repeated {
+                    ngrammap.insert_one(ngram, corpus);
+                }
+            }
+        }
+    }
+    if verbose {
+        writeln!(&mut std::io::stderr(),
+                 "[!] Done generating n-gram map: {} items",
+                 ngrammap.len())
+            .ok();
+    }
+
+    // Map corpuses to unique ngrams
+    let mut fm: HashMap<&LicenseCorpus, OutData> = HashMap::new();
+
+    // Store finished corpuses
+    let mut finished: HashSet<&LicenseCorpus> = HashSet::new();
+
+    // We'll mutate this hashmap at the end of each loop to remove
+    // garbage ngrams (ngrams unique to already finished corpus)
+    let mut allngrams = ngrammap.clone();
+
+    let pgbar: [&str; 4] = ["-", "\\", "|", "/"];
+    let (mut loops, mut prints) = (1, 0);
+
+    loop {
+        if finished.len() == corpuses.len() {
+            break;
+        }
+
+        let last = finished.len();
+        let count = allngrams.len();
+        let mut cleanup: Vec<&NG> = Vec::new();
+        for (i, (ngram, occurences)) in allngrams.iter_mut().enumerate() {
+            if verbose && i % 100 == 0 {
+                write!(&mut std::io::stderr(),
+                       "\r[{}] Processing .. {}/{}",
+                       pgbar[prints % 4],
+                       i,
+                       count)
+                    .ok();
+                prints += 1;
+            }
+
+            // We have an ngram with only a single edge
+            if occurences.len() == 1 {
+                let key = occurences.iter().next().unwrap();
+                if finished.contains(key) || !key.is_stable(ngram) {
+                    cleanup.push(ngram);
+                    continue;
+                }
+
+                let ngrams = fm.entry(key).or_insert_with(|| OutData::new(loops));
+                ngrams.ngrams.push(ngram);
+                ngrams.level = loops;
+                cleanup.push(ngram);
+                if ngrams.ngrams.len() >= header.unique_ngrams {
+                    if verbose {
+                        writeln!(&mut std::io::stderr(), "\r finished: {}", key.name).ok();
+                    }
+                    finished.insert(key);
+                }
+            } else {
+                occurences.retain(|x| !finished.contains(x));
+            }
+        }
+
+        for ng in cleanup {
+            allngrams.remove(ng);
+        }
+
+        if last == finished.len() {
+            // We didn't find a single solution during this iteration, all
+            // remaining corpora share their ngrams with others (identical
+            // texts, texts contained in larger ones, ...). Finish the one
+            // closest to a solution with its rarest ngrams, `discriminate`
+            // tells it apart from the related corpora afterwards. This
+            // doesn't take a level, the next iteration continues on the
+            // same one.
+            let key = corpuses.iter()
+                              .filter(|x| !finished.contains(x))
+                              .max_by_key(|x| {
+                                  (fm.get(x).map_or(0, |d| d.ngrams.len()),
+                                   -(x.ngrams.len() as i64))
+                              })
+                              .unwrap();
+
+            let ngrams = fm.entry(key).or_insert_with(|| OutData::new(loops));
+            for ngram in rarest_ngrams(key, &ngrammap) {
+                if ngrams.ngrams.len() >= header.unique_ngrams {
+                    break;
+                }
+                if !ngrams.ngrams.contains(&ngram) {
+                    ngrams.ngrams.push(ngram);
+                }
+            }
+            ngrams.level = loops;
+
+            if verbose {
+                writeln!(&mut std::io::stderr(), "\r finished (shared ngrams): {}", key.name)
+                    .ok();
+            }
+            finished.insert(key);
+            continue;
+        }
+
+        loops += 1;
+    }
+
+    discriminate(&corpuses, &ngrammap, header.unique_ngrams, &mut fm);
+
+    let mut licenses = save_data(&fm);
+    for corpus in &corpuses {
+        if let (Some(data), Some(out)) = (licenses.get_mut(&corpus.name), fm.get(corpus)) {
+            // Ngrams are windows of the text, ngram `i` starts at token `i`
+            data.offsets = out.ngrams
+                              .iter()
+                              .map(|g| corpus.ngrams.iter().position(|x| x == *g).unwrap_or(0))
+                              .collect();
+        }
+    }
+    for source in sources {
+        if let Some(data) = licenses.get_mut(&source.name) {
+            data.meta = source.meta.clone();
+            data.vars = vars.remove(&source.name).unwrap_or_else(Vec::new);
+        }
+    }
+
+    Ok(CorpusData {
+        header: header.clone(),
+        licenses: licenses,
+        notices: HashMap::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn source(name: &str, text: &str) -> LicenseSource {
+        LicenseSource {
+            name: String::from(name),
+            text: text.to_string(),
+            template: None,
+            meta: None,
+        }
+    }
+
+    /// Text of `n` distinct words starting with `prefix`.
+    fn words(prefix: &str, n: usize) -> String {
+        (0..n).map(|i| format!("{}{}", prefix, i)).collect::<Vec<String>>().join(" ")
+    }
+
+    fn hashes(text: &str, header: &Header) -> HashSet<u64> {
+        get_ngrams(text, header.ngram_size, &header.normalization).iter().map(|x| x.hash).collect()
+    }
+
+    #[test]
+    fn discriminate_contained_license() {
+        let subset = words("alpha", 60);
+        let superset = format!("{} {}", subset, words("extra", 30));
+        let header = Header::default();
+        let data = generate_corpuses(&[source("A", &subset), source("B", &superset)],
+                                     &header,
+                                     false)
+                       .unwrap();
+
+        let (a, b) = (&data.licenses["A"], &data.licenses["B"]);
+        assert_eq!(a.subset_of, vec![String::from("B")]);
+        assert_eq!(a.excludes.len(), header.unique_ngrams);
+        let (own, larger) = (hashes(&subset, &header), hashes(&superset, &header));
+        assert!(a.excludes.iter().all(|g| larger.contains(g) && !own.contains(g)));
+
+        assert!(b.subset_of.is_empty() && b.excludes.is_empty());
+        assert!(b.ngrams.iter().all(|g| !own.contains(g)));
+    }
+}
@@ -0,0 +1,1611 @@
+use std::cmp::{self, Ordering};
+use std::{fmt, str};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, mpsc};
+
+use rustc_serialize::json;
+use threadpool::ThreadPool;
+use walkdir::{DirEntry, WalkDir, WalkDirIterator};
+
+use binary::{self, BinaryCorpus};
+use embedded;
+use layers::{self, Conflict, Layer};
+use corpus::{self, CorpusData, Header, InputVector, NG, TextsData};
+use diff::{self, Edit};
+use fusion::{self, Fusion};
+use index::{Index, NGramMap};
+use normalize::{Normalization, Token};
+use pathex::AbsolutePath;
+use sniff;
+use spdx::expression::{self, Expression};
+use spdx::list::LicenseMeta;
+use spdx::template::VarCheck;
+use ssdeep;
+use error::{ErrorKind, LiceumError, Result};
+use util::{read_bytes, read_file, sha1_hex, write_file};
+use {BINARY_FILE, NGRAMS_FILE, SSDEEP_HASHES, TEXTS_FILE};
+
+/// Number of worker threads used by `Detector::scan_path`.
+const WORKERS: usize = 16;
+
+/// Default minimal ssdeep similarity for a license to be reported.
+pub const SSDEEP_THRESHOLD: u32 = 75;
+
+/// Default minimal confidence of an ngram match to be reported, two out of
+/// three signature ngrams.
+pub const MIN_CONFIDENCE: f64 = 0.6;
+
+/// Minimal number of tokens of text between licenses for it to become a
+/// segment of its own, shorter text only separates the licenses.
+const MIN_SEGMENT_TOKENS: usize = 20;
+
+/// Maximal number of tokens of a text a license without a segment is
+/// aligned against, in multiples of the length of the license text. The
+/// alignment takes quadratic memory in the number of differences.
+const MAX_ALIGN_RATIO: usize = 2;
+
+/// Signature ngrams of a license found in a text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NgramMatch {
+    /// Number of signature ngrams present in the text
+    pub found: usize,
+    /// Number of ngrams in the signature
+    pub total: usize,
+    /// Level of the corpus the signature belongs to
+    pub level: u64,
+}
+
+impl NgramMatch {
+    /// Fraction of signature ngrams present in the text, the confidence of
+    /// the match. Signatures of every level are complete when all their
+    /// ngrams are present, texts they are contained in are ruled out by
+    /// the excludes.
+    pub fn coverage(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.found as f64 / self.total as f64
+    }
+}
+
+/// Form in which a license was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DetectionKind {
+    /// Full text of the license
+    Text,
+    /// Notice referring to the license, such as the header the license
+    /// recommends to put into source files
+    Notice,
+}
+
+impl fmt::Display for DetectionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            DetectionKind::Text => "text",
+            DetectionKind::Notice => "notice",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Part of a scanned text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// First line, starting at 1
+    pub start_line: usize,
+    /// Last line, inclusive
+    pub end_line: usize,
+    /// Byte offset of the start
+    pub start_byte: usize,
+    /// Byte offset just past the end
+    pub end_byte: usize,
+}
+
+/// Byte offsets at which lines of a text start.
+struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> LineIndex {
+        let mut starts = vec![0];
+        starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        LineIndex { starts: starts }
+    }
+
+    /// Line containing byte `offset`, starting at 1.
+    fn line(&self, offset: usize) -> usize {
+        match self.starts.binary_search(&offset) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+
+    /// Bytes `start` up to `end` widened to whole lines, the line break of
+    /// the last line is left out.
+    fn whole_lines(&self, start: usize, end: usize, len: usize) -> (usize, usize) {
+        let first = self.starts[self.line(start) - 1];
+        let last = self.line(if end > start { end - 1 } else { start });
+        match self.starts.get(last) {
+            Some(&next) => (first, next - 1),
+            None => (first, len),
+        }
+    }
+
+    /// Span of bytes `start` up to `end`.
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span {
+            start_line: self.line(start),
+            end_line: self.line(if end > start { end - 1 } else { start }),
+            start_byte: start,
+            end_byte: end,
+        }
+    }
+}
+
+/// Spans of the byte `ranges` of a text `len` bytes long, the region they
+/// cover and the fraction of the text it makes up. Without any ranges the
+/// region is the whole text.
+fn locate(lines: &LineIndex, ranges: Vec<(usize, usize)>, len: usize) -> (Vec<Span>, Span, f64) {
+    let region = match (ranges.first(), ranges.iter().map(|x| x.1).max()) {
+        (Some(&(start, _)), Some(end)) => lines.span(start, end),
+        _ => lines.span(0, len),
+    };
+    let coverage = if len == 0 {
+        0.0
+    } else {
+        (region.end_byte - region.start_byte) as f64 / len as f64
+    };
+    let matches = ranges.into_iter().map(|(start, end)| lines.span(start, end)).collect();
+    (matches, region, coverage)
+}
+
+/// Single license found in a file or text together with the evidence of
+/// both search methods.
+#[derive(Debug, Clone)]
+pub struct Detection {
+    pub license: String,
+    /// Whether the full text or a notice of the license was found, notices
+    /// are searched by ngrams only
+    pub kind: DetectionKind,
+    /// Signature ngrams found, if above the minimal confidence
+    pub ngram: Option<NgramMatch>,
+    /// ssdeep similarity, if above the threshold
+    pub ssdeep: Option<u32>,
+    /// Combined score between 0 and 1 expressing how certain the
+    /// detection is
+    pub confidence: f64,
+    /// Occurrences of the signature ngrams found, ordered by position
+    pub matches: Vec<Span>,
+    /// Part of the text the license was found in, from the first to the last
+    /// signature ngram found or the whole text for ssdeep matches
+    pub region: Span,
+    /// Fraction of the text covered by `region`
+    pub file_coverage: f64,
+    /// Corpus the license comes from, see `Detector::load_layers`
+    pub corpus: Option<String>,
+    /// SPDX identifier of the license, known for corpora generated from
+    /// the SPDX license list
+    pub spdx_id: Option<String>,
+}
+
+/// Consecutive part of a file attributed to a single license.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    /// License of the segment, `None` for text between licenses matching
+    /// none of them
+    pub license: Option<String>,
+    /// Whether the segment holds the full text or a notice of the license
+    pub kind: DetectionKind,
+    /// Signature ngrams found in the segment
+    pub ngram: Option<NgramMatch>,
+    /// ssdeep similarity of the segment, if above the threshold
+    pub ssdeep: Option<u32>,
+    /// Combined score of both, 0 for unknown segments
+    pub confidence: f64,
+    /// Lines of the segment
+    pub region: Span,
+    /// Corpus the license comes from, see `Detector::load_layers`
+    pub corpus: Option<String>,
+}
+
+/// How a passage of a scanned text differs from the license text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// Words added to the license
+    Inserted,
+    /// Words of the license left out
+    Deleted,
+    /// Words of the license replaced by others
+    Substituted,
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            ChangeKind::Inserted => "inserted",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Substituted => "substituted",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Passage of a scanned text differing from the license text.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// Normalized words of the license left out or replaced
+    pub expected: String,
+    /// Normalized words of the scanned text added or found instead
+    pub found: String,
+    /// Part of the scanned text with the change, empty at the position of
+    /// the missing words for deletions
+    pub region: Span,
+    /// Whether the change is confined to a variable or optional part of the
+    /// license template, such as the name of the copyright holder
+    pub variable: bool,
+}
+
+/// Alignment of a part of a scanned text against the text of the license
+/// found in it.
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    pub license: String,
+    pub kind: DetectionKind,
+    /// Part of the scanned text aligned
+    pub region: Span,
+    /// Number of words of the license found unchanged
+    pub matching: usize,
+    /// Number of words of the license
+    pub total: usize,
+    pub changes: Vec<Change>,
+}
+
+impl Explanation {
+    /// Predicate determining whether the license is modified beyond its
+    /// variable and optional parts.
+    pub fn is_modified(&self) -> bool {
+        self.changes.iter().any(|x| !x.variable)
+    }
+}
+
+/// Copy of a license text or notice found by `Detector::segment_text`,
+/// ranges are given in tokens.
+struct Located {
+    license: String,
+    kind: DetectionKind,
+    ngram: NgramMatch,
+    /// From the first to the last signature ngram found
+    core: (usize, usize),
+    /// Expected extent of the whole text
+    extent: (usize, usize),
+}
+
+impl Located {
+    /// Predicate determining whether the copies can't both be present,
+    /// either their signature ngrams overlap or one is a part of the other
+    /// text of the same license, like the notice in the appendix of a
+    /// license.
+    fn overlaps(&self, other: &Located) -> bool {
+        let within = |a: (usize, usize), b: (usize, usize)| a.0 >= b.0 && a.1 <= b.1;
+        (self.core.0 < other.core.1 && other.core.0 < self.core.1) ||
+        (self.license == other.license &&
+         (within(self.core, other.extent) || within(other.core, self.extent)))
+    }
+}
+
+/// `SPDX-License-Identifier` tag found in a file.
+#[derive(Debug, Clone)]
+pub struct LicenseTag {
+    /// Line of the tag, starting at 1
+    pub line: usize,
+    /// Value of the tag as written in the file
+    pub value: String,
+    /// Parsed license expression, `None` if the value isn't valid
+    pub expression: Option<Expression>,
+    /// Reason the value couldn't be parsed
+    pub error: Option<String>,
+    /// License identifiers of the expression unknown to the corpus
+    pub unknown: Vec<String>,
+    /// License identifiers of the expression deprecated by SPDX
+    pub deprecated: Vec<String>,
+    /// License identifiers of the expression on the SPDX license list, as
+    /// listed in the corpus
+    pub listed: Vec<String>,
+}
+
+impl LicenseTag {
+    /// Predicate determining whether the tag is a valid expression of
+    /// known licenses.
+    pub fn is_valid(&self) -> bool {
+        self.expression.is_some() && self.unknown.is_empty()
+    }
+}
+
+/// Licenses found in a single file.
+#[derive(Debug, Clone)]
+pub struct FileResult {
+    pub file: String,
+    /// Hex encoded SHA1 digest of the file contents
+    pub sha1: String,
+    pub detections: Vec<Detection>,
+    /// License expressions declared by the file
+    pub tags: Vec<LicenseTag>,
+    /// Licenses of the file in order of appearance, empty unless
+    /// segmentation is enabled and some license is found
+    pub segments: Vec<Segment>,
+    /// Differences from the texts of the licenses found, empty unless
+    /// explanations are enabled
+    pub explanations: Vec<Explanation>,
+}
+
+/// Failure to scan a single file, the scan itself continues.
+#[derive(Debug, Clone)]
+pub struct FileError {
+    pub file: String,
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl FileError {
+    fn new(file: &str, error: &LiceumError) -> FileError {
+        FileError {
+            file: String::from(file),
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Outcome of `Detector::scan_path`.
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    /// All scanned files including those without licenses, ordered by path
+    pub files: Vec<FileResult>,
+    /// Files which couldn't be scanned, ordered by path
+    pub errors: Vec<FileError>,
+    /// Minimal ssdeep similarity the scan ran with, for licenses without a
+    /// threshold of their own
+    pub ssdeep_threshold: u32,
+}
+
+/// License detector holding both the ngram corpus and ssdeep hashes.
+///
+/// The detector is cheap to clone, clones share the loaded data.
+#[derive(Clone)]
+pub struct Detector {
+    header: Header,
+    licenses: Arc<InputVector>,
+    notices: Arc<InputVector>,
+    // signature ngrams of `licenses` and `notices`
+    license_index: Arc<Index>,
+    notice_index: Arc<Index>,
+    detect_notices: bool,
+    hashes: Arc<Vec<(String, String)>>,
+    min_confidence: f64,
+    fusion: Fusion,
+    ssdeep_threshold: u32,
+    // license: ssdeep threshold overriding `ssdeep_threshold`
+    thresholds: Arc<HashMap<String, u32>>,
+    segment: bool,
+    // texts of the licenses and notices, used to explain matches
+    texts: Arc<TextsData>,
+    explain: bool,
+    validate_vars: bool,
+    // license: template variables ngram matches are validated against,
+    // empty unless `validate_vars` is set
+    var_checks: Arc<HashMap<String, Vec<VarCheck>>>,
+    // names of the combined corpora, empty for generated detectors
+    corpora: Arc<Vec<String>>,
+    // (kind, license): corpus it comes from
+    origins: Arc<HashMap<(DetectionKind, String), String>>,
+    conflicts: Arc<Vec<Conflict>>,
+    // binary corpus `licenses` and `notices` were indexed from, their data
+    // holds no ngrams then
+    mapped: Option<Arc<BinaryCorpus>>,
+}
+
+// ngram: byte ranges of its occurrences in a text
+type Positions = NGramMap<Vec<(usize, usize)>>;
+
+// license, signature ngrams found, byte ranges of their occurrences
+type SignatureMatch = (String, NgramMatch, Vec<(usize, usize)>);
+
+/// Positions of all ngrams of `ngrams` extracted together with their byte
+/// ranges.
+fn positions(ngrams: &[(NG, usize, usize)]) -> Positions {
+    let mut positions: Positions = NGramMap::default();
+    for &(ngram, start, end) in ngrams {
+        positions.entry(ngram).or_insert_with(Vec::new).push((start, end));
+    }
+    positions
+}
+
+/// Split occurrences of signature ngrams, given as (start, index of the
+/// ngram in the signature, end) ordered by start, into the copies of the
+/// text they come from, a copy ends before any of its ngrams repeats.
+fn copies(occurrences: &[(usize, usize, usize)]) -> Vec<&[(usize, usize, usize)]> {
+    let mut copies: Vec<&[(usize, usize, usize)]> = Vec::new();
+    let mut seen: HashSet<usize> = HashSet::new();
+    let mut first = 0;
+    for (i, &(_, ngram, _)) in occurrences.iter().enumerate() {
+        if !seen.insert(ngram) {
+            copies.push(&occurrences[first..i]);
+            seen.clear();
+            seen.insert(ngram);
+            first = i;
+        }
+    }
+    if first < occurrences.len() {
+        copies.push(&occurrences[first..]);
+    }
+    copies
+}
+
+/// Check that ssdeep similarity `threshold` is within 0 and 100.
+fn check_threshold(threshold: u32) -> Result<()> {
+    if threshold > 100 {
+        return Err(LiceumError::Usage(format!("ssdeep threshold must be between 0 and 100, \
+                                               got {}",
+                                              threshold)));
+    }
+    Ok(())
+}
+
+/// Predicate determining whether the path is hidden.
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry.path()
+         .to_str()
+         .map_or(false, |s| s.contains("/."))
+}
+
+/// Predicate determining whether the path is a text file or directory
+/// entry. Due to the recursive nature of the walker we can't filter out
+/// directories here.
+fn is_text_or_dirent(entry: &DirEntry) -> bool {
+    if entry.file_type().is_dir() {
+        return true;
+    }
+
+    entry.file_type().is_file() && entry.path().to_str().map_or(false, sniff::is_text_file)
+}
+
+impl Detector {
+    /// Create detector from ngram corpus `data` and ssdeep `hashes` given as
+    /// `hash, filename` pairs.
+    pub fn new(data: &CorpusData, hashes: Vec<(String, String)>) -> Detector {
+        let hashes = Detector::hash_names(|x| data.licenses.contains_key(x), hashes);
+        let licenses = corpus::load_data(&data.licenses);
+        let notices = corpus::load_data(&data.notices);
+        let indices = (Index::new(&licenses), Index::new(&notices));
+        Detector::indexed(data.header.clone(), licenses, notices, indices, hashes)
+    }
+
+    /// Create detector from the binary `corpus` named `name`, its ngrams
+    /// are indexed straight from the mapped tables, which are kept to
+    /// `save` the detector. ssdeep `hashes` and license `texts` are given
+    /// as stored in a data directory.
+    pub fn from_binary(name: &str,
+                       corpus: BinaryCorpus,
+                       hashes: &str,
+                       texts: Option<&str>)
+                       -> Result<Detector> {
+        let texts: TextsData = match texts {
+            Some(t) => try!(json::decode(t)),
+            None => TextsData::default(),
+        };
+        let licenses = corpus.licenses();
+        let notices = corpus.notices();
+        let hashes = Detector::hash_names(|x| licenses.iter().any(|l| l.file == x),
+                                          ssdeep::parse_hashes(hashes));
+
+        let mut origins: HashMap<(DetectionKind, String), String> = HashMap::new();
+        for &(kind, vector) in &[(DetectionKind::Text, &licenses),
+                                 (DetectionKind::Notice, &notices)] {
+            for ic in vector {
+                origins.insert((kind, ic.file.clone()), String::from(name));
+            }
+        }
+
+        let indices = (corpus.license_index(), corpus.notice_index());
+        let mut detector = Detector::indexed(corpus.header().clone(),
+                                             licenses,
+                                             notices,
+                                             indices,
+                                             hashes);
+        detector.texts = Arc::new(texts);
+        detector.corpora = Arc::new(vec![String::from(name)]);
+        detector.origins = Arc::new(origins);
+        detector.mapped = Some(Arc::new(corpus));
+        Ok(detector)
+    }
+
+    /// Detector of `licenses` and `notices` with their `indices` built
+    /// already.
+    fn indexed(header: Header,
+               licenses: InputVector,
+               notices: InputVector,
+               indices: (Index, Index),
+               hashes: Vec<(String, String)>)
+               -> Detector {
+        let thresholds = licenses.iter()
+                                 .filter_map(|x| {
+                                     x.data.ssdeep_threshold.map(|t| (x.file.clone(), t))
+                                 })
+                                 .collect();
+        Detector {
+            header: header,
+            license_index: Arc::new(indices.0),
+            notice_index: Arc::new(indices.1),
+            licenses: Arc::new(licenses),
+            notices: Arc::new(notices),
+            detect_notices: true,
+            hashes: Arc::new(hashes),
+            min_confidence: MIN_CONFIDENCE,
+            fusion: Fusion::default(),
+            ssdeep_threshold: SSDEEP_THRESHOLD,
+            thresholds: Arc::new(thresholds),
+            segment: false,
+            texts: Arc::new(TextsData::default()),
+            explain: false,
+            validate_vars: false,
+            var_checks: Arc::new(HashMap::new()),
+            corpora: Arc::new(Vec::new()),
+            origins: Arc::new(HashMap::new()),
+            conflicts: Arc::new(Vec::new()),
+            mapped: None,
+        }
+    }
+
+    /// ssdeep `hashes` named by the corpus, older data files list license
+    /// file paths. Names of corpora are told by `is_corpus`.
+    fn hash_names<F>(is_corpus: F, hashes: Vec<(String, String)>) -> Vec<(String, String)>
+        where F: Fn(&str) -> bool
+    {
+        hashes.into_iter()
+              .map(|(hash, name)| {
+                  if is_corpus(&name) {
+                      return (hash, name);
+                  }
+                  match corpus::corpus_name(&name) {
+                      Some(x) => (hash, x),
+                      None => (hash, name),
+                  }
+              })
+              .collect()
+    }
+
+    /// Generate the detector data from license texts in `data_dir` using
+    /// generation parameters from `header`, see `corpus::generate`.
+    pub fn generate(data_dir: &str,
+                    notices_dir: Option<&str>,
+                    header: &Header,
+                    verbose: bool)
+                    -> Result<Detector> {
+        let generated = try!(corpus::generate(data_dir, notices_dir, header, verbose));
+        let mut detector = Detector::new(&generated.data, generated.hashes);
+        detector.texts = Arc::new(generated.texts);
+        Ok(detector)
+    }
+
+    /// Load the detector data previously saved into `data_dir`, the binary
+    /// corpus is preferred to the JSON one. Missing ssdeep hashes only
+    /// disable the ssdeep search and missing license texts the
+    /// explanations.
+    pub fn load(data_dir: &str) -> Result<Detector> {
+        Detector::load_layers(&[data_dir])
+    }
+
+    /// Load and combine the detector data of all `data_dirs`, see
+    /// `layers::combine`. Licenses found are attributed to the data
+    /// directory they come from. A single binary corpus is used from the
+    /// mapped file as it is, combined corpora are decoded as combining
+    /// rewrites their signatures.
+    pub fn load_layers<S: AsRef<str>>(data_dirs: &[S]) -> Result<Detector> {
+        let read = |data_dir: &str| {
+            let hashes_path = Path::new(data_dir).join(SSDEEP_HASHES);
+            let hashes = read_file(&hashes_path.to_string_lossy())
+                             .unwrap_or_else(|_| String::new());
+            let texts_path = Path::new(data_dir).join(TEXTS_FILE);
+            (hashes, read_file(&texts_path.to_string_lossy()).ok())
+        };
+
+        if data_dirs.len() == 1 {
+            let data_dir = data_dirs[0].as_ref();
+            let file = Path::new(data_dir).join(BINARY_FILE);
+            if file.is_file() {
+                let corpus = try!(BinaryCorpus::open(&file));
+                let (hashes, texts) = read(data_dir);
+                return Detector::from_binary(data_dir,
+                                             corpus,
+                                             &hashes,
+                                             texts.as_ref().map(|x| x.as_str()));
+            }
+        }
+
+        let mut layers: Vec<Layer> = Vec::new();
+        for dir in data_dirs {
+            let data_dir = dir.as_ref();
+            let decoded = try!(binary::read_data(data_dir));
+            let (hashes, texts) = read(data_dir);
+            layers.push(try!(Detector::layer(data_dir,
+                                             decoded,
+                                             &hashes,
+                                             texts.as_ref().map(|x| x.as_str()))));
+        }
+        Detector::from_layers(layers)
+    }
+
+    /// Load the default corpus embedded into the binary by the
+    /// `embedded-corpus` feature.
+    pub fn embedded() -> Result<Detector> {
+        let corpus = match embedded::corpus() {
+            Some(x) => x,
+            None => {
+                return Err(LiceumError::Usage(String::from("No corpus is embedded, build with \
+                                                            the embedded-corpus feature")))
+            }
+        };
+
+        let texts = if corpus.texts.is_empty() {
+            None
+        } else {
+            Some(corpus.texts)
+        };
+        if corpus.binary {
+            let binary = try!(BinaryCorpus::from_static(corpus.ngrams));
+            return Detector::from_binary("embedded", binary, corpus.hashes, texts);
+        }
+
+        let d = try!(str::from_utf8(corpus.ngrams)
+                         .map_err(|e| LiceumError::Encoding(e.to_string())));
+        let decoded: CorpusData = try!(json::decode(d));
+        try!(corpus::validate(&decoded));
+        let layer = try!(Detector::layer("embedded", decoded, corpus.hashes, texts));
+        Detector::from_layers(vec![layer])
+    }
+
+    /// Layer named `name` of the corpus `data`, ssdeep `hashes` and license
+    /// `texts` as stored in a data directory.
+    fn layer(name: &str, data: CorpusData, hashes: &str, texts: Option<&str>) -> Result<Layer> {
+        let texts = match texts {
+            Some(t) => try!(json::decode(t)),
+            None => TextsData::default(),
+        };
+        Ok(Layer {
+            name: String::from(name),
+            hashes: Detector::hash_names(|x| data.licenses.contains_key(x),
+                                         ssdeep::parse_hashes(hashes)),
+            data: data,
+            texts: texts,
+        })
+    }
+
+    /// Detector of the corpora of all `layers` combined.
+    pub fn from_layers(layers: Vec<Layer>) -> Result<Detector> {
+        let names = layers.iter().map(|x| x.name.clone()).collect();
+        let combined = try!(layers::combine(layers));
+        let mut detector = Detector::new(&combined.data, combined.hashes);
+        detector.texts = Arc::new(combined.texts);
+        detector.corpora = Arc::new(names);
+        detector.origins = Arc::new(combined.origins);
+        detector.conflicts = Arc::new(combined.conflicts);
+        Ok(detector)
+    }
+
+    /// Save the detector data into `data_dir` so that it can be loaded
+    /// again with `Detector::load`.
+    pub fn save(&self, data_dir: &str) -> Result<()> {
+        let ngrams = Path::new(data_dir).join(NGRAMS_FILE);
+        let mut data = match self.mapped {
+            Some(ref m) => m.corpus_data(),
+            None => {
+                CorpusData {
+                    header: self.header.clone(),
+                    licenses: corpus::store_data(&self.licenses),
+                    notices: corpus::store_data(&self.notices),
+                }
+            }
+        };
+        for (license, v) in data.licenses.iter_mut() {
+            v.ssdeep_threshold = self.thresholds.get(license).cloned();
+        }
+        try!(write_file(&ngrams.to_string_lossy(), &corpus::to_json(&data)));
+
+        let hashes = Path::new(data_dir).join(SSDEEP_HASHES);
+        try!(write_file(&hashes.to_string_lossy(), &ssdeep::format_hashes(&self.hashes)));
+
+        let texts = Path::new(data_dir).join(TEXTS_FILE);
+        try!(write_file(&texts.to_string_lossy(), &json::encode(&*self.texts).unwrap()));
+        Ok(())
+    }
+
+    /// Parameters the corpus was generated with.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Fail with `LiceumError::Mismatch` unless the corpus was generated
+    /// with ngrams of `size` tokens.
+    pub fn require_ngram_size(&self, size: usize) -> Result<()> {
+        if size != self.header.ngram_size {
+            return Err(LiceumError::Mismatch(format!("corpus uses ngrams of size {}, search \
+                                                      requested {}",
+                                                     self.header.ngram_size,
+                                                     size)));
+        }
+        Ok(())
+    }
+
+    /// Fail with `LiceumError::Mismatch` unless the corpus was generated
+    /// with the normalization steps of `norm`.
+    pub fn require_normalization(&self, norm: &Normalization) -> Result<()> {
+        if *norm != self.header.normalization {
+            return Err(LiceumError::Mismatch(format!("corpus uses normalization {}, search \
+                                                      requested {}",
+                                                     self.header.normalization,
+                                                     norm)));
+        }
+        Ok(())
+    }
+
+    /// Minimal confidence of reported ngram matches.
+    pub fn min_confidence(&self) -> f64 {
+        self.min_confidence
+    }
+
+    /// Report ngram matches with at least `confidence`, `1.0` requires all
+    /// signature ngrams to be present.
+    pub fn set_min_confidence(&mut self, confidence: f64) {
+        self.min_confidence = confidence;
+    }
+
+    /// Policy reconciling ngram and ssdeep evidence.
+    pub fn fusion(&self) -> Fusion {
+        self.fusion
+    }
+
+    /// Reconcile ngram and ssdeep evidence according to `fusion`.
+    pub fn set_fusion(&mut self, fusion: Fusion) {
+        self.fusion = fusion;
+    }
+
+    /// Minimal ssdeep similarity of reported licenses without a threshold
+    /// of their own.
+    pub fn ssdeep_threshold(&self) -> u32 {
+        self.ssdeep_threshold
+    }
+
+    /// Report ssdeep matches with similarity above `threshold` unless the
+    /// license has a threshold of its own, similarities range from 0 to
+    /// 100.
+    pub fn set_ssdeep_threshold(&mut self, threshold: u32) -> Result<()> {
+        try!(check_threshold(threshold));
+        self.ssdeep_threshold = threshold;
+        Ok(())
+    }
+
+    /// Names of the corpora combined by `load_layers`, in order.
+    pub fn corpora(&self) -> &[String] {
+        &self.corpora
+    }
+
+    /// Conflicts between licenses of the corpora combined by
+    /// `load_layers`.
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// Corpus `license` of `kind` was loaded from.
+    fn origin(&self, kind: DetectionKind, license: &str) -> Option<String> {
+        self.origins.get(&(kind, String::from(license))).cloned()
+    }
+
+    /// Per-license ssdeep thresholds, stored in the corpus by `save`.
+    pub fn license_thresholds(&self) -> &HashMap<String, u32> {
+        &self.thresholds
+    }
+
+    /// Override the ssdeep threshold for `license`, short permissive
+    /// licenses usually need a different one than long copyleft texts.
+    pub fn set_license_threshold(&mut self, license: &str, threshold: u32) -> Result<()> {
+        try!(check_threshold(threshold));
+        Arc::make_mut(&mut self.thresholds).insert(String::from(license), threshold);
+        Ok(())
+    }
+
+    /// Predicate determining whether files are split into segments of
+    /// single licenses.
+    pub fn segment(&self) -> bool {
+        self.segment
+    }
+
+    /// Split scanned files into segments of single licenses, see
+    /// `Detector::segment_text`.
+    pub fn set_segment(&mut self, segment: bool) {
+        self.segment = segment;
+    }
+
+    /// Predicate determining whether matches are explained.
+    pub fn explain(&self) -> bool {
+        self.explain
+    }
+
+    /// Align scanned files against the texts of the licenses found in them,
+    /// see `Detector::explain_text`.
+    pub fn set_explain(&mut self, explain: bool) {
+        self.explain = explain;
+    }
+
+    /// Predicate determining whether license notices are searched for.
+    pub fn detect_notices(&self) -> bool {
+        self.detect_notices
+    }
+
+    /// Search for license notices besides full license texts, a notice
+    /// isn't reported when the full text of its license is found.
+    pub fn set_detect_notices(&mut self, detect: bool) {
+        self.detect_notices = detect;
+    }
+
+    /// Predicate determining whether ngram matches are validated against
+    /// the template variables.
+    pub fn validate_vars(&self) -> bool {
+        self.validate_vars
+    }
+
+    /// Validate ngram matches against the `match` patterns of the template
+    /// variables, stored for corpora generated from SPDX license templates.
+    /// A match is rejected when the text around a variable is found but the
+    /// variable doesn't match its pattern.
+    pub fn set_validate_vars(&mut self, validate: bool) {
+        let mut checks: HashMap<String, Vec<VarCheck>> = HashMap::new();
+        if validate {
+            for ic in self.licenses.iter().filter(|x| !x.data.vars.is_empty()) {
+                let compiled = ic.data.vars.iter().filter_map(|x| x.compile()).collect();
+                checks.insert(ic.file.clone(), compiled);
+            }
+        }
+        self.validate_vars = validate;
+        self.var_checks = Arc::new(checks);
+    }
+
+    /// Texts of the licenses and notices of the corpus, empty for data
+    /// directories saved without them.
+    pub fn texts(&self) -> &TextsData {
+        &self.texts
+    }
+
+    /// SPDX metadata of `license`, known for corpora generated from the
+    /// SPDX license list.
+    pub fn license_meta(&self, license: &str) -> Option<&LicenseMeta> {
+        self.licenses
+            .iter()
+            .find(|x| x.file == license)
+            .and_then(|x| x.data.meta.as_ref())
+    }
+
+    /// SPDX identifier of `license`, see `license_meta`.
+    fn spdx_id(&self, license: &str) -> Option<String> {
+        self.license_meta(license).map(|m| m.id.clone())
+    }
+
+    /// Predicate determining whether `id` is the SPDX identifier of a
+    /// license of the corpus, as listed.
+    fn is_listed(&self, id: &str) -> bool {
+        self.licenses.iter().any(|x| x.data.meta.as_ref().map_or(false, |m| m.id == id))
+    }
+
+    /// Predicate determining whether `id` names a license of the corpus,
+    /// identifiers are compared without regard to case.
+    fn is_known(&self, id: &str) -> bool {
+        let id = id.to_lowercase();
+        self.licenses.iter().any(|x| {
+            x.file.to_lowercase() == id ||
+            x.data.meta.as_ref().map_or(false, |m| m.id.to_lowercase() == id)
+        })
+    }
+
+    /// Names of all licenses known to the detector, ordered by level.
+    pub fn licenses(&self) -> Vec<&str> {
+        self.licenses.iter().map(|x| x.file.as_str()).collect()
+    }
+
+    /// Signatures of `corpora` matching ngrams of `text` with at least the
+    /// minimal confidence, together with the byte ranges of all
+    /// occurrences of the matching signature ngrams. Candidates are looked
+    /// up in `index` built from `corpora`.
+    fn match_signatures(&self,
+                        corpora: &InputVector,
+                        index: &Index,
+                        positions: &Positions,
+                        text: &str)
+                        -> Vec<SignatureMatch> {
+        let mut matches: Vec<SignatureMatch> = Vec::new();
+        for c in index.candidates(positions.keys()) {
+            // Licenses contained in larger texts are ruled out by the rest
+            // of the larger text
+            if c.excluded() {
+                continue;
+            }
+
+            let ic = &corpora[c.corpus];
+            let found: Vec<&Vec<(usize, usize)>> = c.found
+                                                    .iter()
+                                                    .filter_map(|&(_, g)| positions.get(&g))
+                                                    .collect();
+            let m = NgramMatch {
+                found: found.len(),
+                total: c.total,
+                level: ic.data.level,
+            };
+            if m.found > 0 && m.coverage() >= self.min_confidence &&
+               (ic.data.vars.is_empty() || self.vars_match(&ic.file, text)) {
+                let mut ranges: Vec<(usize, usize)> = found.into_iter()
+                                                           .flat_map(|x| x.iter().cloned())
+                                                           .collect();
+                ranges.sort();
+                matches.push((ic.file.clone(), m, ranges));
+            }
+        }
+        matches
+    }
+
+    /// Search `text` for licenses using both ngrams and ssdeep hashes and
+    /// for license notices using ngrams, every license is listed once,
+    /// ordered by decreasing confidence.
+    pub fn scan_text(&self, text: &str) -> Vec<Detection> {
+        // license: (ngram evidence, ssdeep evidence)
+        let mut evidence: BTreeMap<String, (Option<NgramMatch>, Option<u32>)> = BTreeMap::new();
+
+        // license: byte ranges of the signature ngrams found
+        let mut ranges: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+
+        let ng = corpus::get_spanned_ngrams(text,
+                                            self.header.ngram_size,
+                                            &self.header.normalization);
+        let positions = positions(&ng);
+        let matched = self.match_signatures(&self.licenses, &self.license_index, &positions, text);
+        for (license, m, r) in matched {
+            evidence.entry(license.clone()).or_insert((None, None)).0 = Some(m);
+            ranges.insert(license, r);
+        }
+
+        let hash = ssdeep::hash_bytes(text.as_bytes());
+        for &(ref template_hash, ref name) in self.hashes.iter() {
+            let license = name.clone();
+            let score = ssdeep::similarity(&hash, template_hash);
+            if score > self.threshold(&license) {
+                let entry = evidence.entry(license).or_insert((None, None));
+                entry.1 = Some(entry.1.map_or(score, |x| x.max(score)));
+            }
+        }
+
+        let lines = LineIndex::new(text);
+        let any_ngram = evidence.values().any(|x| x.0.is_some());
+        let mut found: Vec<Detection> = Vec::new();
+        for (license, (ngram, ssdeep)) in evidence {
+            if !self.fusion.accepts(ngram.is_some(), ssdeep.is_some(), any_ngram) {
+                continue;
+            }
+
+            let confidence = fusion::combine(ngram.as_ref().map(|x| x.coverage()),
+                                             ssdeep.map(|x| x as f64 / 100.0));
+            let (matches, region, coverage) =
+                locate(&lines, ranges.remove(&license).unwrap_or_else(Vec::new), text.len());
+            found.push(Detection {
+                corpus: self.origin(DetectionKind::Text, &license),
+                spdx_id: self.spdx_id(&license),
+                license: license,
+                kind: DetectionKind::Text,
+                ngram: ngram,
+                ssdeep: ssdeep,
+                confidence: confidence,
+                matches: matches,
+                region: region,
+                file_coverage: coverage,
+            });
+        }
+
+        if self.detect_notices {
+            let matched =
+                self.match_signatures(&self.notices, &self.notice_index, &positions, text);
+            for (license, m, r) in matched {
+                if found.iter().any(|x| x.license == license) {
+                    continue;
+                }
+                let (matches, region, coverage) = locate(&lines, r, text.len());
+                found.push(Detection {
+                    corpus: self.origin(DetectionKind::Notice, &license),
+                    spdx_id: self.spdx_id(&license),
+                    license: license,
+                    kind: DetectionKind::Notice,
+                    confidence: m.coverage(),
+                    ngram: Some(m),
+                    ssdeep: None,
+                    matches: matches,
+                    region: region,
+                    file_coverage: coverage,
+                });
+            }
+        }
+
+        // Of equally confident licenses the one of the lower level first
+        let level = |x: &Detection| x.ngram.as_ref().map_or(u64::max_value(), |m| m.level);
+        found.sort_by(|a, b| {
+            match b.confidence.partial_cmp(&a.confidence) {
+                Some(Ordering::Equal) | None => level(a).cmp(&level(b)),
+                Some(x) => x,
+            }
+        });
+        found
+    }
+
+    /// Minimal ssdeep similarity of `license`.
+    fn threshold(&self, license: &str) -> u32 {
+        self.thresholds
+            .get(license)
+            .cloned()
+            .unwrap_or(self.ssdeep_threshold)
+    }
+
+    /// Split `text`, such as a file of bundled third party notices, into
+    /// consecutive segments each holding a single license. Every copy of a
+    /// license text or notice is located by its signature ngrams and
+    /// stretched over the whole text using the offsets of the ngrams within
+    /// it, of copies overlapping each other the more confident and then the
+    /// longer one is kept. Segments are widened to whole lines and confirmed
+    /// by ssdeep, text left between them is matched by ssdeep alone and
+    /// reported as unknown when nothing matches. Empty when no license is
+    /// found.
+    pub fn segment_text(&self, text: &str) -> Vec<Segment> {
+        let n = self.header.ngram_size;
+        let tokens = self.header.normalization.spanned_tokens(text);
+        let ng = corpus::token_ngrams(&tokens, n);
+        // ngram: tokens it starts at
+        let mut starts: NGramMap<Vec<usize>> = NGramMap::default();
+        for (i, x) in ng.iter().enumerate() {
+            starts.entry(x.0).or_insert_with(Vec::new).push(i);
+        }
+        let bytes = |(start, end): (usize, usize)| (tokens[start].start, tokens[end - 1].end);
+
+        let mut corpora = vec![(&self.licenses, &self.license_index, DetectionKind::Text)];
+        if self.detect_notices {
+            corpora.push((&self.notices, &self.notice_index, DetectionKind::Notice));
+        }
+
+        let mut found: Vec<Located> = Vec::new();
+        for (vector, index, kind) in corpora {
+            for c in index.candidates(starts.keys()) {
+                let ic = &vector[c.corpus];
+                let mut occurrences: Vec<(usize, usize, usize)> = Vec::new();
+                for &(i, g) in &c.found {
+                    if let Some(p) = starts.get(&g) {
+                        occurrences.extend(p.iter().map(|&t| (t, i, t + n)));
+                    }
+                }
+                occurrences.sort();
+
+                for copy in copies(&occurrences) {
+                    let m = NgramMatch {
+                        found: copy.len(),
+                        total: c.total,
+                        level: ic.data.level,
+                    };
+                    let (first, last) = (copy[0], copy[copy.len() - 1]);
+                    let core = (first.0, copy.iter().map(|x| x.2).max().unwrap_or(first.2));
+                    let extent = match (ic.data.offsets.get(first.1), ic.data.offsets.get(last.1)) {
+                        (Some(&a), Some(&b)) if ic.data.tokens > b => {
+                            (first.0.saturating_sub(a),
+                             cmp::min(last.0 + ic.data.tokens - b, tokens.len()))
+                        }
+                        _ => core,
+                    };
+                    let extent = (cmp::min(extent.0, core.0), cmp::max(extent.1, core.1));
+
+                    // Copies of licenses contained in larger texts are ruled
+                    // out by the rest of the larger text, within the copy as
+                    // other copies of the text may hold a license of its own
+                    let excluded = c.excludes.iter().any(|g| {
+                        starts.get(g).map_or(false, |p| {
+                            p.iter().any(|&t| t >= extent.0 && t < extent.1)
+                        })
+                    });
+                    if excluded {
+                        continue;
+                    }
+
+                    let (start, end) = bytes(extent);
+                    if m.coverage() >= self.min_confidence &&
+                       (ic.data.vars.is_empty() || self.vars_match(&ic.file, &text[start..end])) {
+                        found.push(Located {
+                            license: ic.file.clone(),
+                            kind: kind,
+                            ngram: m,
+                            core: core,
+                            extent: extent,
+                        });
+                    }
+                }
+            }
+        }
+
+        found.sort_by(|a, b| {
+            match b.ngram.coverage().partial_cmp(&a.ngram.coverage()) {
+                Some(Ordering::Equal) | None => {
+                    (b.extent.1 - b.extent.0).cmp(&(a.extent.1 - a.extent.0))
+                }
+                Some(x) => x,
+            }
+        });
+        let mut kept: Vec<Located> = Vec::new();
+        for c in found {
+            if !kept.iter().any(|k| c.overlaps(k)) {
+                kept.push(c);
+            }
+        }
+        kept.sort_by_key(|x| x.core.0);
+
+        // Copies don't reach into the neighbouring ones
+        for i in 0..kept.len() {
+            if i > 0 {
+                kept[i].extent.0 = cmp::max(kept[i].extent.0, kept[i - 1].extent.1);
+            }
+            if i + 1 < kept.len() {
+                kept[i].extent.1 = cmp::min(kept[i].extent.1, kept[i + 1].core.0);
+            }
+        }
+
+        let lines = LineIndex::new(text);
+        let mut segments: Vec<Segment> = Vec::new();
+        if kept.is_empty() {
+            return segments;
+        }
+        let mut last = 0;
+        for c in kept {
+            let (start, end) = bytes(c.extent);
+            let (start, end) = lines.whole_lines(start, end, text.len());
+            let start = cmp::max(start, last);
+            segments.extend(self.unknown_segment(text, last, start, &lines));
+
+            let ssdeep = if c.kind == DetectionKind::Text {
+                let hash = ssdeep::hash_bytes(text[start..end].as_bytes());
+                self.hashes
+                    .iter()
+                    .filter(|x| x.1 == c.license)
+                    .map(|x| ssdeep::similarity(&hash, &x.0))
+                    .filter(|&x| x > self.threshold(&c.license))
+                    .max()
+            } else {
+                None
+            };
+            segments.push(Segment {
+                corpus: self.origin(c.kind, &c.license),
+                license: Some(c.license),
+                kind: c.kind,
+                confidence: fusion::combine(Some(c.ngram.coverage()),
+                                            ssdeep.map(|x| x as f64 / 100.0)),
+                ngram: Some(c.ngram),
+                ssdeep: ssdeep,
+                region: lines.span(start, end),
+            });
+            last = end;
+        }
+        segments.extend(self.unknown_segment(text, last, text.len(), &lines));
+        segments
+    }
+
+    /// Align `text` against the texts of the `detections` in it and report
+    /// the passages differing from them. Every one of `segments` of a found
+    /// license is aligned separately together with some of the text around
+    /// it, as segments may end early in modified licenses, licenses without
+    /// a segment are aligned against the whole text unless it is longer
+    /// than `MAX_ALIGN_RATIO` times the license. Words added before the
+    /// start and after the end of the license aren't part of it and aren't
+    /// reported. Licenses whose text isn't stored in the data directory
+    /// aren't explained.
+    pub fn explain_text(&self,
+                        text: &str,
+                        detections: &[Detection],
+                        segments: &[Segment])
+                        -> Vec<Explanation> {
+        if detections.is_empty() {
+            return Vec::new();
+        }
+        let tokens = self.header.normalization.spanned_tokens(text);
+        let lines = LineIndex::new(text);
+        // Tokens starting at or after byte `x`
+        let first = |x: usize| tokens.iter().take_while(|t| t.start < x).count();
+
+        let mut explanations: Vec<Explanation> = Vec::new();
+        for d in detections {
+            let texts = match d.kind {
+                DetectionKind::Text => &self.texts.licenses,
+                DetectionKind::Notice => &self.texts.notices,
+            };
+            let reference = match texts.get(&d.license) {
+                Some(x) => corpus::reference_tokens(x, &self.header.normalization),
+                None => continue,
+            };
+            let slack = reference.0.len() / 4 + self.header.ngram_size;
+
+            let mut windows: Vec<(usize, usize)> = Vec::new();
+            for (i, s) in segments.iter().enumerate() {
+                if s.kind != d.kind || s.license.as_ref() != Some(&d.license) {
+                    continue;
+                }
+                let low = if i > 0 { first(segments[i - 1].region.end_byte) } else { 0 };
+                let high = segments.get(i + 1).map_or(tokens.len(), |x| first(x.region.start_byte));
+                windows.push((cmp::max(first(s.region.start_byte).saturating_sub(slack), low),
+                              cmp::min(first(s.region.end_byte) + slack, high)));
+            }
+            if windows.is_empty() {
+                if tokens.len() > MAX_ALIGN_RATIO * reference.0.len() + slack {
+                    continue;
+                }
+                windows.push((0, tokens.len()));
+            }
+            for (start, end) in windows {
+                explanations.push(self.explain_tokens(&tokens[start..end], d, &reference, &lines));
+            }
+        }
+        explanations
+    }
+
+    /// Align `tokens` of a scanned text against the tokens of the license
+    /// of `d` and the token ranges of its variable parts.
+    fn explain_tokens(&self,
+                      tokens: &[Token],
+                      d: &Detection,
+                      reference: &(Vec<String>, Vec<(usize, usize)>),
+                      lines: &LineIndex)
+                      -> Explanation {
+        let (ref expected, ref variable) = *reference;
+        let expected: Vec<&str> = expected.iter().map(|x| x.as_str()).collect();
+        let found: Vec<&str> = tokens.iter().map(|x| x.text.as_str()).collect();
+        let edits = diff::diff(&expected, &found);
+
+        let in_variable = |i: usize| variable.iter().any(|&(s, e)| s <= i && i < e);
+        let end = tokens.last().map_or(0, |x| x.end);
+        let mut changes: Vec<Change> = Vec::new();
+        // Tokens of `found` equal to the license
+        let mut kept: Vec<usize> = Vec::new();
+        let (mut a, mut b) = (0, 0);
+        let mut i = 0;
+        while i < edits.len() {
+            if let Edit::Keep(x, y) = edits[i] {
+                kept.push(y);
+                a = x + 1;
+                b = y + 1;
+                i += 1;
+                continue;
+            }
+
+            // Consecutive edits form a single change, expected tokens
+            // start_a..a replaced by found tokens start_b..b
+            let (start_a, start_b) = (a, b);
+            while i < edits.len() {
+                match edits[i] {
+                    Edit::Keep(..) => break,
+                    Edit::Delete(x) => a = x + 1,
+                    Edit::Insert(y) => b = y + 1,
+                }
+                i += 1;
+            }
+
+            let (deleted, inserted) = (a > start_a, b > start_b);
+            if !deleted && (start_a == 0 || start_a == expected.len()) {
+                continue;
+            }
+            let kind = match (deleted, inserted) {
+                (true, true) => ChangeKind::Substituted,
+                (true, false) => ChangeKind::Deleted,
+                _ => ChangeKind::Inserted,
+            };
+            let region = if inserted {
+                lines.span(tokens[start_b].start, tokens[b - 1].end)
+            } else {
+                let at = tokens.get(start_b).map_or(end, |x| x.start);
+                lines.span(at, at)
+            };
+            let variable = (start_a..a).all(&in_variable) &&
+                           (!inserted || variable.iter().any(|&(s, e)| s <= start_a && a <= e));
+            changes.push(Change {
+                kind: kind,
+                expected: expected[start_a..a].join(" "),
+                found: found[start_b..b].join(" "),
+                region: region,
+                variable: variable,
+            });
+        }
+
+        let region = match (kept.first(), kept.last()) {
+            (Some(&x), Some(&y)) => lines.span(tokens[x].start, tokens[y].end),
+            _ => lines.span(tokens.first().map_or(end, |x| x.start), end),
+        };
+        Explanation {
+            license: d.license.clone(),
+            kind: d.kind,
+            region: region,
+            matching: kept.len(),
+            total: expected.len(),
+            changes: changes,
+        }
+    }
+
+    /// Segment of the text between licenses from byte `start` up to `end`
+    /// attributed to the license most similar by ssdeep, if long enough.
+    fn unknown_segment(&self,
+                       text: &str,
+                       start: usize,
+                       end: usize,
+                       lines: &LineIndex)
+                       -> Option<Segment> {
+        let part = &text[start..end];
+        if self.header.normalization.tokens(part).len() < MIN_SEGMENT_TOKENS {
+            return None;
+        }
+        let start = start + (part.len() - part.trim_left().len());
+        let end = end - (part.len() - part.trim_right().len());
+
+        let hash = ssdeep::hash_bytes(text[start..end].as_bytes());
+        let best = self.hashes
+                       .iter()
+                       .map(|x| (ssdeep::similarity(&hash, &x.0), &x.1))
+                       .filter(|&(score, license)| score > self.threshold(license))
+                       .max();
+        Some(Segment {
+            license: best.map(|x| x.1.clone()),
+            kind: DetectionKind::Text,
+            ngram: None,
+            ssdeep: best.map(|x| x.0),
+            confidence: best.map_or(0.0, |x| x.0 as f64 / 100.0),
+            region: lines.span(start, end),
+            corpus: best.and_then(|x| self.origin(DetectionKind::Text, x.1)),
+        })
+    }
+
+    /// Predicate determining whether no template variable of `license` is
+    /// present in `text` with a value not matching its pattern.
+    fn vars_match(&self, license: &str, text: &str) -> bool {
+        self.var_checks
+            .get(license)
+            .map_or(true, |checks| checks.iter().all(|x| x.check(text) != Some(false)))
+    }
+
+    /// Find `SPDX-License-Identifier` tags in `text` and validate their
+    /// license identifiers against the corpus, `LicenseRef-` identifiers
+    /// are always accepted.
+    pub fn scan_tags(&self, text: &str) -> Vec<LicenseTag> {
+        expression::find_tags(text)
+            .into_iter()
+            .map(|(line, value)| {
+                let mut tag = LicenseTag {
+                    line: line,
+                    value: value,
+                    expression: None,
+                    error: None,
+                    unknown: Vec::new(),
+                    deprecated: Vec::new(),
+                    listed: Vec::new(),
+                };
+
+                match Expression::parse(&tag.value) {
+                    Ok(e) => {
+                        for l in e.licenses() {
+                            if expression::is_license_ref(&l.id) {
+                                continue;
+                            }
+                            if !self.is_known(&l.id) {
+                                tag.unknown.push(l.id.clone());
+                                continue;
+                            }
+                            if self.license_meta(&l.id).map_or(false, |m| m.deprecated) {
+                                tag.deprecated.push(l.id.clone());
+                            }
+                            if self.is_listed(&l.id) {
+                                tag.listed.push(l.id.clone());
+                            }
+                        }
+                        tag.expression = Some(e);
+                    }
+                    Err(e) => tag.error = Some(e),
+                }
+                tag
+            })
+            .collect()
+    }
+
+    /// Search `file` for licenses, the result is keyed by the canonical path.
+    /// Text in other encodings than UTF-8 is decoded by `sniff::decode`,
+    /// reported byte ranges refer to the decoded text then.
+    pub fn scan_file(&self, file: &str) -> Result<FileResult> {
+        let p = Path::new(file).canonical_path();
+        let data = try!(read_bytes(file));
+        let text = match sniff::decode(&data) {
+            Some(x) => x,
+            None => {
+                return Err(LiceumError::Encoding(String::from("text doesn't match its byte \
+                                                                order mark")))
+            }
+        };
+        let detections = self.scan_text(&text);
+        let segments = if self.segment || (self.explain && !detections.is_empty()) {
+            self.segment_text(&text)
+        } else {
+            Vec::new()
+        };
+        let explanations = if self.explain {
+            self.explain_text(&text, &detections, &segments)
+        } else {
+            Vec::new()
+        };
+        Ok(FileResult {
+            file: p.to_string_lossy().into_owned(),
+            sha1: sha1_hex(&data),
+            detections: detections,
+            tags: self.scan_tags(&text),
+            segments: if self.segment { segments } else { Vec::new() },
+            explanations: explanations,
+        })
+    }
+
+    /// Search all non-hidden text files under `path`. Files which can't be
+    /// read or decoded are reported in `ScanResult::errors` and the scan
+    /// carries on with the rest.
+    pub fn scan_path(&self, path: &str) -> ScanResult {
+        let pool = ThreadPool::new(WORKERS);
+        let mut paths: Vec<String> = Vec::new();
+        let mut errors: Vec<FileError> = Vec::new();
+
+        let walker = WalkDir::new(path).into_iter();
+        for p in walker.filter_entry(|e| !is_hidden(e) && is_text_or_dirent(e)) {
+            let file_entry = match p {
+                Ok(x) => x,
+                Err(e) => {
+                    let file = e.path()
+                                .map_or(String::from(path), |x| x.to_string_lossy().into_owned());
+                    errors.push(FileError::new(&file, &LiceumError::from(e)));
+                    continue;
+                }
+            };
+
+            if !file_entry.file_type().is_file() {
+                continue;
+            }
+
+            match file_entry.path().to_str() {
+                Some(file) => paths.push(String::from(file)),
+                None => {
+                    let file = file_entry.path().to_string_lossy();
+                    let e = LiceumError::Encoding(String::from("path is not valid UTF-8"));
+                    errors.push(FileError::new(&file, &e));
+                }
+            }
+        }
+
+        let rx = {
+            let (tx, rx) = mpsc::channel();
+
+            for p in paths {
+                let (tx, detector) = (tx.clone(), self.clone());
+
+                pool.execute(move || {
+                    let result = detector.scan_file(&p).map_err(|e| FileError::new(&p, &e));
+                    tx.send(result).ok();
+                });
+            }
+
+            rx
+        };
+
+        let mut files: Vec<FileResult> = Vec::new();
+        while let Ok(item) = rx.recv() {
+            match item {
+                Ok(x) => files.push(x),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        files.sort_by(|a, b| a.file.cmp(&b.file));
+        errors.sort_by(|a, b| a.file.cmp(&b.file));
+        ScanResult {
+            files: files,
+            errors: errors,
+            ssdeep_threshold: self.ssdeep_threshold,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corpus::{self, Header, LicenseSource};
+
+    /// Text of `n` distinct words starting with `prefix`.
+    fn words(prefix: &str, n: usize) -> String {
+        (0..n).map(|i| format!("{}{}", prefix, i)).collect::<Vec<String>>().join(" ")
+    }
+
+    fn source(name: &str, text: &str) -> LicenseSource {
+        LicenseSource {
+            name: String::from(name),
+            text: String::from(text),
+            template: None,
+            meta: None,
+        }
+    }
+
+    fn detector(sources: &[LicenseSource]) -> Detector {
+        let data = corpus::generate_corpuses(sources, &Header::default(), false).unwrap();
+        Detector::new(&data, Vec::new())
+    }
+
+    #[test]
+    fn scan_modified_text() {
+        // Nine words make the three ngrams of the signature, the first
+        // word is part of one of them and the second of two
+        let a = words("alpha", 9);
+        let detector = detector(&[source("A", &a), source("B", &words("beta", 60))]);
+        let found = |text: &str| -> Vec<(String, f64)> {
+            detector.scan_text(text)
+                    .into_iter()
+                    .map(|x| (x.license, x.confidence))
+                    .collect()
+        };
+
+        assert_eq!(found(&a.replace("alpha0 ", "edited ")),
+                   vec![(String::from("A"), 2.0 / 3.0)]);
+        assert!(found(&a.replace("alpha1 ", "edited ")).is_empty());
+    }
+
+    #[test]
+    fn scan_exact_matches() {
+        let (a, b) = (words("alpha", 60), words("beta", 60));
+        let mut detector = detector(&[source("A", &a), source("B", &b)]);
+        detector.set_min_confidence(1.0);
+
+        let found = detector.scan_text(&format!("header\n{}\nfooter", b));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].license, "B");
+        assert_eq!(found[0].ngram.as_ref().map(|x| x.coverage()), Some(1.0));
+        assert!(detector.scan_text(&words("gamma", 60)).is_empty());
+    }
+
+    /// Detector of license A and of a larger license B containing it,
+    /// with the text of A and of B modified so that it is found with lower
+    /// confidence. The signature of B holds all its unique ngrams, those
+    /// the modification leaves out don't depend on the generation.
+    fn subset_and_superset() -> (Detector, String, String) {
+        let subset = words("alpha", 60);
+        let superset = format!("{} {}", subset, words("extra", 30));
+        let header = Header { unique_ngrams: 40, ..Header::default() };
+        let sources = [source("A", &subset), source("B", &superset)];
+        let data = corpus::generate_corpuses(&sources, &header, false).unwrap();
+        let modified = superset.replace("extra12", "modified");
+        (Detector::new(&data, Vec::new()), subset, modified)
+    }
+
+    #[test]
+    fn scan_rules_out_contained_license() {
+        let (detector, _, modified) = subset_and_superset();
+        let found: Vec<String> = detector.scan_text(&modified)
+                                         .into_iter()
+                                         .map(|x| x.license)
+                                         .collect();
+        assert_eq!(found, vec!["B"]);
+    }
+
+    #[test]
+    fn segments_rule_out_contained_license() {
+        let (detector, subset, modified) = subset_and_superset();
+        let licenses = |text: &str| -> Vec<Option<String>> {
+            detector.segment_text(text).into_iter().map(|x| x.license).collect()
+        };
+        assert_eq!(licenses(&modified), vec![Some(String::from("B"))]);
+        assert_eq!(licenses(&format!("{}\n\n{}", subset, modified)),
+                   vec![Some(String::from("A")), Some(String::from("B"))]);
+    }
+}
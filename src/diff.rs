@@ -0,0 +1,160 @@
+/// Single step of an edit script turning one sequence into another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    /// `a[i]` is equal to `b[j]`
+    Keep(usize, usize),
+    /// `a[i]` is left out
+    Delete(usize),
+    /// `b[j]` is added
+    Insert(usize),
+}
+
+/// Shortest edit script turning `a` into `b`, computed by the Myers
+/// difference algorithm. Edits are ordered by position in both sequences.
+pub fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+
+    // Furthest x reached on every diagonal k = x - y, indexed by k + max
+    let mut v: Vec<isize> = vec![0; 2 * max as usize + 2];
+    // Diagonals -d..d of `v` after every step d
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let at = |k: isize| (k + max) as usize;
+
+    'search: for d in 0..max + 1 {
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+                v[at(k + 1)]
+            } else {
+                v[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+
+            if x >= n && y >= m {
+                trace.push(v[at(-d)..at(d) + 1].to_vec());
+                break 'search;
+            }
+            k += 2;
+        }
+        trace.push(v[at(-d)..at(d) + 1].to_vec());
+    }
+
+    let mut edits: Vec<Edit> = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        // Diagonals of the previous step start at -(d - 1)
+        let prev = |k: isize| trace[d as usize - 1][(k + d - 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && prev(k - 1) < prev(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = prev(prev_k);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Keep(x as usize, y as usize));
+        }
+        if x == prev_x {
+            y -= 1;
+            edits.push(Edit::Insert(y as usize));
+        } else {
+            x -= 1;
+            edits.push(Edit::Delete(x as usize));
+        }
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        edits.push(Edit::Keep(x as usize, y as usize));
+    }
+
+    edits.reverse();
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Apply `edits` to `a`, checking they refer to the right elements.
+    fn apply(a: &[char], b: &[char], edits: &[Edit]) -> Vec<char> {
+        let mut out: Vec<char> = Vec::new();
+        let (mut x, mut y) = (0, 0);
+        for e in edits {
+            match *e {
+                Edit::Keep(i, j) => {
+                    assert_eq!((i, j), (x, y));
+                    assert_eq!(a[i], b[j]);
+                    out.push(a[i]);
+                    x += 1;
+                    y += 1;
+                }
+                Edit::Delete(i) => {
+                    assert_eq!(i, x);
+                    x += 1;
+                }
+                Edit::Insert(j) => {
+                    assert_eq!(j, y);
+                    out.push(b[j]);
+                    y += 1;
+                }
+            }
+        }
+        assert_eq!((x, y), (a.len(), b.len()));
+        out
+    }
+
+    /// Number of deletions and insertions of the diff of `a` and `b`.
+    fn distance(a: &str, b: &str) -> usize {
+        let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+        let edits = diff(&a, &b);
+        assert_eq!(apply(&a, &b, &edits), b);
+        edits.iter()
+             .filter(|x| match **x {
+                 Edit::Keep(..) => false,
+                 _ => true,
+             })
+             .count()
+    }
+
+    #[test]
+    fn empty() {
+        let none: [char; 0] = [];
+        assert!(diff(&none, &none).is_empty());
+        assert_eq!(diff(&none, &['a']), vec![Edit::Insert(0)]);
+        assert_eq!(diff(&['a'], &none), vec![Edit::Delete(0)]);
+    }
+
+    #[test]
+    fn equal() {
+        assert_eq!(diff(&['a', 'b'], &['a', 'b']),
+                   vec![Edit::Keep(0, 0), Edit::Keep(1, 1)]);
+    }
+
+    #[test]
+    fn single_changes() {
+        assert_eq!(diff(&['a', 'b', 'c'], &['a', 'c']),
+                   vec![Edit::Keep(0, 0), Edit::Delete(1), Edit::Keep(2, 1)]);
+        assert_eq!(diff(&['a', 'c'], &['a', 'b', 'c']),
+                   vec![Edit::Keep(0, 0), Edit::Insert(1), Edit::Keep(1, 2)]);
+    }
+
+    #[test]
+    fn shortest_script() {
+        // Example of the Myers paper
+        assert_eq!(distance("abcabba", "cbabac"), 5);
+        assert_eq!(distance("abc", "xyz"), 6);
+        assert_eq!(distance("kitten", "sitting"), 5);
+        assert_eq!(distance("the quick fox", "the quick brown fox"), 6);
+    }
+}
@@ -0,0 +1,39 @@
+/// Whether the binary carries a default corpus, see `corpus`.
+pub const AVAILABLE: bool = cfg!(feature = "embedded-corpus");
+
+/// Data directory embedded into the binary at build time.
+pub struct Corpus {
+    /// Contents of `BINARY_FILE` or `NGRAMS_FILE`
+    pub ngrams: &'static [u8],
+    /// Whether `ngrams` are in the binary format
+    pub binary: bool,
+    /// Contents of `SSDEEP_HASHES`
+    pub hashes: &'static str,
+    /// Contents of `TEXTS_FILE`, empty when the directory had none
+    pub texts: &'static str,
+}
+
+#[cfg(feature = "embedded-corpus")]
+mod data {
+    include!(concat!(env!("OUT_DIR"), "/embedded.rs"));
+}
+
+/// Corpus embedded by the `embedded-corpus` feature, generated from
+/// `licenses` of the source tree or taken from `LICEUM_CORPUS` when
+/// building.
+#[cfg(feature = "embedded-corpus")]
+pub fn corpus() -> Option<Corpus> {
+    Some(Corpus {
+        ngrams: data::NGRAMS,
+        binary: data::BINARY,
+        hashes: data::HASHES,
+        texts: data::TEXTS,
+    })
+}
+
+/// Corpus embedded by the `embedded-corpus` feature, the binary was built
+/// without it.
+#[cfg(not(feature = "embedded-corpus"))]
+pub fn corpus() -> Option<Corpus> {
+    None
+}
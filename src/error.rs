@@ -0,0 +1,111 @@
+use std::error::Error;
+use std::{fmt, io, result};
+use std::string::FromUtf8Error;
+
+use rustc_serialize::json;
+use walkdir;
+
+/// Errors produced while generating corpora or scanning files.
+#[derive(Debug)]
+pub enum LiceumError {
+    /// Reading or writing a file failed
+    Io(io::Error),
+    /// File or path is not valid UTF-8 text
+    Encoding(String),
+    /// Directory traversal failed
+    Walk(walkdir::Error),
+    /// Data corpus couldn't be decoded
+    Corpus(json::DecoderError),
+    /// Data corpus is inconsistent or doesn't match requested parameters
+    Mismatch(String),
+    /// Invalid combination of options or arguments
+    Usage(String),
+}
+
+/// Coarse classification of `LiceumError` used in per-file reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Io,
+    Encoding,
+    Walk,
+    Corpus,
+    Usage,
+}
+
+pub type Result<T> = result::Result<T, LiceumError>;
+
+impl LiceumError {
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            LiceumError::Io(_) => ErrorKind::Io,
+            LiceumError::Encoding(_) => ErrorKind::Encoding,
+            LiceumError::Walk(_) => ErrorKind::Walk,
+            LiceumError::Corpus(_) => ErrorKind::Corpus,
+            LiceumError::Mismatch(_) => ErrorKind::Corpus,
+            LiceumError::Usage(_) => ErrorKind::Usage,
+        }
+    }
+}
+
+impl fmt::Display for LiceumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LiceumError::Io(ref e) => write!(f, "I/O error: {}", e),
+            LiceumError::Encoding(ref s) => write!(f, "invalid encoding: {}", s),
+            LiceumError::Walk(ref e) => write!(f, "directory traversal failed: {}", e),
+            LiceumError::Corpus(ref e) => write!(f, "invalid data corpus: {}", e),
+            LiceumError::Mismatch(ref s) => write!(f, "data corpus mismatch: {}", s),
+            LiceumError::Usage(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl Error for LiceumError {
+    fn description(&self) -> &str {
+        match *self {
+            LiceumError::Io(ref e) => e.description(),
+            LiceumError::Encoding(_) => "invalid encoding",
+            LiceumError::Walk(ref e) => e.description(),
+            LiceumError::Corpus(ref e) => e.description(),
+            LiceumError::Mismatch(ref s) => s,
+            LiceumError::Usage(ref s) => s,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            ErrorKind::Io => "io",
+            ErrorKind::Encoding => "encoding",
+            ErrorKind::Walk => "walk",
+            ErrorKind::Corpus => "corpus",
+            ErrorKind::Usage => "usage",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl From<io::Error> for LiceumError {
+    fn from(e: io::Error) -> LiceumError {
+        LiceumError::Io(e)
+    }
+}
+
+impl From<FromUtf8Error> for LiceumError {
+    fn from(e: FromUtf8Error) -> LiceumError {
+        LiceumError::Encoding(e.to_string())
+    }
+}
+
+impl From<walkdir::Error> for LiceumError {
+    fn from(e: walkdir::Error) -> LiceumError {
+        LiceumError::Walk(e)
+    }
+}
+
+impl From<json::DecoderError> for LiceumError {
+    fn from(e: json::DecoderError) -> LiceumError {
+        LiceumError::Corpus(e)
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt;
+
+/// Policy reconciling ngram and ssdeep evidence when the two search
+/// methods disagree about the licenses present in a text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fusion {
+    /// Report licenses found by either method
+    Union,
+    /// Report only licenses found by both methods
+    Intersection,
+    /// Report licenses found by ssdeep alone only if no ngram match was
+    /// found in the text, ssdeep tends to match closely related licenses
+    PreferNgram,
+}
+
+impl Default for Fusion {
+    fn default() -> Fusion {
+        Fusion::PreferNgram
+    }
+}
+
+impl Fusion {
+    /// Parse policy name, one of `union`, `intersection` or `prefer-ngram`.
+    pub fn parse(spec: &str) -> Result<Fusion, String> {
+        match spec.trim() {
+            "union" => Ok(Fusion::Union),
+            "intersection" => Ok(Fusion::Intersection),
+            "prefer-ngram" => Ok(Fusion::PreferNgram),
+            _ => Err(format!("Unknown fusion policy: {}", spec)),
+        }
+    }
+
+    /// Predicate determining whether a license with the given evidence is
+    /// reported, `any_ngram` tells whether ngrams found any license in the
+    /// text at all.
+    pub fn accepts(&self, ngram: bool, ssdeep: bool, any_ngram: bool) -> bool {
+        match *self {
+            Fusion::Union => ngram || ssdeep,
+            Fusion::Intersection => ngram && ssdeep,
+            Fusion::PreferNgram => ngram || (ssdeep && !any_ngram),
+        }
+    }
+}
+
+impl fmt::Display for Fusion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Fusion::Union => "union",
+            Fusion::Intersection => "intersection",
+            Fusion::PreferNgram => "prefer-ngram",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Combine confidences of independent methods, agreement of both raises
+/// the score above either of them.
+pub fn combine(ngram: Option<f64>, ssdeep: Option<f64>) -> f64 {
+    match (ngram, ssdeep) {
+        (Some(a), Some(b)) => 1.0 - (1.0 - a) * (1.0 - b),
+        (Some(a), None) | (None, Some(a)) => a,
+        (None, None) => 0.0,
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+use corpus::{InputVector, NG};
+
+/// Hasher of ngrams, which are hashes already and are used as they are.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentityHasher(u64);
+
+impl Hasher for IdentityHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = self.0.rotate_left(8) ^ *b as u64;
+        }
+    }
+
+    fn write_u64(&mut self, x: u64) {
+        self.0 = x;
+    }
+}
+
+/// Map keyed by ngrams without hashing them again.
+pub type NGramMap<V> = HashMap<NG, V, BuildHasherDefault<IdentityHasher>>;
+
+/// Role of an ngram in the corpus it is indexed for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// `ngrams[i]` of the signature
+    Signature(usize),
+    /// `excludes[i]`, its presence rules the corpus out
+    Exclude(usize),
+}
+
+/// Occurrence of an ngram in the signature or excludes of a corpus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Posting {
+    /// Position of the corpus in the indexed `InputVector`
+    pub corpus: usize,
+    pub role: Role,
+}
+
+/// Signature ngrams found in a text for a single corpus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// Position of the corpus in the indexed `InputVector`
+    pub corpus: usize,
+    /// Signature ngrams found with their indices, in order of lookup
+    pub found: Vec<(usize, NG)>,
+    /// Number of ngrams in the signature
+    pub total: usize,
+    /// Excludes of the corpus found
+    pub excludes: Vec<NG>,
+}
+
+impl Candidate {
+    /// Predicate determining whether an exclude of the corpus was found.
+    pub fn excluded(&self) -> bool {
+        !self.excludes.is_empty()
+    }
+}
+
+/// Inverted index from ngrams to the corpora whose signature or excludes
+/// contain them, a text is matched against all corpora by a single lookup
+/// per distinct ngram instead of probing every corpus.
+#[derive(Debug, Clone, Default)]
+pub struct Index {
+    postings: NGramMap<Vec<Posting>>,
+    // number of signature ngrams of every corpus
+    totals: Vec<usize>,
+}
+
+impl Index {
+    /// Index signatures and excludes of all `corpora`.
+    pub fn new(corpora: &InputVector) -> Index {
+        let mut index = Index::default();
+        for ic in corpora {
+            index.push(ic.data.ngrams.iter().cloned(), ic.data.excludes.iter().cloned());
+        }
+        index
+    }
+
+    /// Index `signature` and `excludes` of the corpus following the ones
+    /// indexed so far.
+    pub fn push<I, J>(&mut self, signature: I, excludes: J)
+        where I: IntoIterator<Item = NG>,
+              J: IntoIterator<Item = NG>
+    {
+        let c = self.totals.len();
+        let mut total = 0;
+        for (i, ngram) in signature.into_iter().enumerate() {
+            self.add(ngram, c, Role::Signature(i));
+            total += 1;
+        }
+        for (i, ngram) in excludes.into_iter().enumerate() {
+            self.add(ngram, c, Role::Exclude(i));
+        }
+        self.totals.push(total);
+    }
+
+    fn add(&mut self, ngram: NG, corpus: usize, role: Role) {
+        self.postings.entry(ngram).or_insert_with(Vec::new).push(Posting {
+            corpus: corpus,
+            role: role,
+        });
+    }
+
+    /// Number of distinct ngrams.
+    pub fn len(&self) -> usize {
+        self.postings.len()
+    }
+
+    /// Predicate determining whether nothing is indexed.
+    pub fn is_empty(&self) -> bool {
+        self.postings.is_empty()
+    }
+
+    /// Postings of `ngram`.
+    pub fn lookup(&self, ngram: &NG) -> &[Posting] {
+        self.postings.get(ngram).map_or(&[], |x| x.as_slice())
+    }
+
+    /// Corpora with at least one signature ngram among the distinct
+    /// `ngrams` of a text, ordered by position in the indexed vector.
+    pub fn candidates<'a, I>(&self, ngrams: I) -> Vec<Candidate>
+        where I: IntoIterator<Item = &'a NG>
+    {
+        let mut candidates: HashMap<usize, Candidate> = HashMap::new();
+        for ngram in ngrams {
+            for p in self.lookup(ngram) {
+                let c = candidates.entry(p.corpus).or_insert_with(|| {
+                    Candidate {
+                        corpus: p.corpus,
+                        found: Vec::new(),
+                        total: self.totals[p.corpus],
+                        excludes: Vec::new(),
+                    }
+                });
+                match p.role {
+                    Role::Signature(i) => c.found.push((i, *ngram)),
+                    Role::Exclude(_) => c.excludes.push(*ngram),
+                }
+            }
+        }
+
+        let mut out: Vec<Candidate> = candidates.into_iter()
+                                                .map(|(_, c)| c)
+                                                .filter(|c| !c.found.is_empty())
+                                                .collect();
+        out.sort_by_key(|c| c.corpus);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use corpus::{InData, InputCorpus, InputVector, NG};
+    use ngram::NGram;
+    use super::{Candidate, Index};
+
+    fn ngrams(hashes: &[u64]) -> Vec<NG> {
+        hashes.iter().map(|&x| NGram::from_hash(x)).collect()
+    }
+
+    fn corpus(file: &str, signature: &[u64], excludes: &[u64]) -> InputCorpus {
+        InputCorpus {
+            file: String::from(file),
+            data: InData {
+                ngrams: ngrams(signature),
+                level: 1,
+                excludes: ngrams(excludes),
+                subset_of: Vec::new(),
+                ssdeep_threshold: None,
+                meta: None,
+                vars: Vec::new(),
+                offsets: (0..signature.len()).collect(),
+                tokens: signature.len(),
+            },
+        }
+    }
+
+    /// Candidates found by probing every corpus for every ngram of `text`.
+    fn probe(corpora: &InputVector, text: &[NG]) -> Vec<Candidate> {
+        let mut out: Vec<Candidate> = Vec::new();
+        for (c, ic) in corpora.iter().enumerate() {
+            let mut candidate = Candidate {
+                corpus: c,
+                found: Vec::new(),
+                total: ic.data.ngrams.len(),
+                excludes: Vec::new(),
+            };
+            for ngram in text {
+                for (i, g) in ic.data.ngrams.iter().enumerate() {
+                    if g == ngram {
+                        candidate.found.push((i, *ngram));
+                    }
+                }
+                if ic.data.excludes.contains(ngram) {
+                    candidate.excludes.push(*ngram);
+                }
+            }
+            if !candidate.found.is_empty() {
+                out.push(candidate);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn candidates_match_probe() {
+        // B and C share ngrams with A, B and C exclude ngrams of A
+        let corpora = vec![corpus("A", &[1, 2, 3, 4], &[]),
+                           corpus("B", &[3, 4, 5], &[1]),
+                           corpus("C", &[4, 6, 7], &[2, 9]),
+                           corpus("D", &[8], &[])];
+        let index = Index::new(&corpora);
+        assert_eq!(index.len(), 9);
+        assert!(index.lookup(&NGram::from_hash(10)).is_empty());
+
+        let texts: &[&[u64]] = &[&[1, 2, 3, 4, 5, 6, 7, 8, 9],
+                                 &[5, 4, 3],
+                                 &[4],
+                                 &[1, 2, 9],
+                                 &[6, 2, 10],
+                                 &[10, 11]];
+        for text in texts {
+            let text = ngrams(text);
+            assert_eq!(index.candidates(&text), probe(&corpora, &text));
+        }
+    }
+
+    #[test]
+    fn candidates_excluded() {
+        let corpora = vec![corpus("A", &[1, 2, 3], &[]), corpus("B", &[3, 4], &[1, 2])];
+        let index = Index::new(&corpora);
+
+        let found = index.candidates(&ngrams(&[3, 4]));
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().all(|c| !c.excluded()));
+
+        let found = index.candidates(&ngrams(&[1, 2, 3, 4]));
+        assert_eq!(found.iter().map(|c| c.excluded()).collect::<Vec<_>>(), [false, true]);
+        assert_eq!(found[1].excludes, ngrams(&[1, 2]));
+        assert_eq!((found[1].found.len(), found[1].total), (2, 2));
+
+        // excludes alone don't make a candidate
+        let found = index.candidates(&ngrams(&[1]));
+        assert_eq!(found.iter().map(|c| c.corpus).collect::<Vec<_>>(), [0]);
+    }
+}
@@ -0,0 +1,505 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use corpus::{self, CorpusData, Header, JsonInMap, LicenseText, TextsData, VecInData};
+use detector::DetectionKind;
+use spdx::template::Template;
+use error::{LiceumError, Result};
+
+/// Corpus of a single data directory, one of the layers combined by
+/// `combine`.
+pub struct Layer {
+    /// Name the licenses of the layer are attributed to
+    pub name: String,
+    pub data: CorpusData,
+    /// ssdeep hashes and the licenses they belong to
+    pub hashes: Vec<(String, String)>,
+    pub texts: TextsData,
+}
+
+/// How licenses of two combined corpora conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Both corpora define a license of the same name, the later corpus
+    /// is used
+    Duplicate,
+    /// Signature ngrams of the license appear in the text of a license of
+    /// the other corpus
+    Overlap,
+}
+
+impl fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            ConflictKind::Duplicate => "duplicate",
+            ConflictKind::Overlap => "overlap",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Conflict between licenses of two combined corpora.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    /// Whether the licenses are full texts or notices
+    pub detection: DetectionKind,
+    pub license: String,
+    pub corpus: String,
+    /// License of the other corpus, same as `license` for duplicates
+    pub other: String,
+    pub other_corpus: String,
+    /// Whether the licenses are told apart after combining, the shared
+    /// ngrams of the signature are replaced or ngrams of the other text
+    /// become excludes of the license
+    pub resolved: bool,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ConflictKind::Duplicate => {
+                write!(f,
+                       "{} {} of {} is overridden by {}",
+                       self.detection,
+                       self.license,
+                       self.other_corpus,
+                       self.corpus)
+            }
+            ConflictKind::Overlap => {
+                try!(write!(f,
+                            "{} {} of {} overlaps with {} of {}",
+                            self.detection,
+                            self.license,
+                            self.corpus,
+                            self.other,
+                            self.other_corpus));
+                if !self.resolved {
+                    try!(write!(f, ", they can't be told apart"));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Corpora of all layers combined into one.
+pub struct Combined {
+    pub data: CorpusData,
+    pub hashes: Vec<(String, String)>,
+    pub texts: TextsData,
+    // (kind, license): name of the layer it comes from
+    pub origins: HashMap<(DetectionKind, String), String>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Licenses of one kind merged from all layers.
+struct Merged {
+    kind: DetectionKind,
+    // license: (layer, corpus)
+    corpora: BTreeMap<String, (usize, VecInData)>,
+    texts: HashMap<String, LicenseText>,
+}
+
+/// Merge licenses of `kind` selected by `select` from all `layers`, later
+/// layers win over earlier ones.
+fn merge<F>(layers: &[Layer],
+            kind: DetectionKind,
+            select: F,
+            conflicts: &mut Vec<Conflict>)
+            -> Merged
+    where F: Fn(&Layer) -> (&JsonInMap, &HashMap<String, LicenseText>)
+{
+    let mut corpora: BTreeMap<String, (usize, VecInData)> = BTreeMap::new();
+    for (i, layer) in layers.iter().enumerate() {
+        let (licenses, _) = select(layer);
+        let mut names: Vec<&String> = licenses.keys().collect();
+        names.sort();
+        for name in names {
+            if let Some(&(j, _)) = corpora.get(name) {
+                conflicts.push(Conflict {
+                    kind: ConflictKind::Duplicate,
+                    detection: kind,
+                    license: name.clone(),
+                    corpus: layer.name.clone(),
+                    other: name.clone(),
+                    other_corpus: layers[j].name.clone(),
+                    resolved: true,
+                });
+            }
+            corpora.insert(name.clone(), (i, licenses[name].clone()));
+        }
+    }
+
+    let texts = corpora.iter()
+                       .filter_map(|(name, &(i, _))| {
+                           select(&layers[i]).1.get(name).map(|t| (name.clone(), t.clone()))
+                       })
+                       .collect();
+    Merged {
+        kind: kind,
+        corpora: corpora,
+        texts: texts,
+    }
+}
+
+/// Make signatures of `merged` unique across layers.
+///
+/// Signatures are unique within the corpus they were generated in, but
+/// may appear in texts of another corpus. Shared ngrams are replaced by
+/// ngrams of the license text no other license has, a signature contained
+/// in the other text as a whole is kept and ngrams present only in the
+/// other text become its excludes, as in generation. Texts missing from
+/// the data directory are represented by their signatures, shared ngrams
+/// are only dropped then and a signature left with less than
+/// `header.unique_ngrams` ngrams doesn't tell the licenses apart.
+fn discriminate(merged: &mut Merged,
+                layers: &[Layer],
+                header: &Header,
+                conflicts: &mut Vec<Conflict>) {
+    // license: ngrams of its text in order
+    let ngrams: HashMap<String, Vec<u64>> =
+        merged.corpora
+              .iter()
+              .map(|(name, &(_, ref data))| {
+                  // All layers share the ngram size and normalization,
+                  // ngrams stored with the texts are the same as here
+                  let ngrams = match merged.texts.get(name) {
+                      Some(&LicenseText { ngrams: Some(ref x), .. }) => x.clone(),
+                      Some(t) => {
+                          corpus::get_ngrams(&t.text, header.ngram_size, &header.normalization)
+                              .into_iter()
+                              .map(|x| x.hash)
+                              .collect()
+                      }
+                      None => data.ngrams.clone(),
+                  };
+                  (name.clone(), ngrams)
+              })
+              .collect();
+    let sets: HashMap<&String, HashSet<u64>> = ngrams.iter()
+                                                     .map(|(k, v)| (k, v.iter().cloned().collect()))
+                                                     .collect();
+    // license: ngrams of the required text of its template
+    let fixed: HashMap<&String, HashSet<u64>> =
+        merged.texts
+              .iter()
+              .filter_map(|(name, t)| {
+                  t.template.as_ref().and_then(|x| Template::parse(x).ok()).map(|x| {
+                      (name, corpus::fixed_ngrams(&x, header).iter().map(|g| g.hash).collect())
+                  })
+              })
+              .collect();
+
+    let names: Vec<(String, usize)> = merged.corpora
+                                            .iter()
+                                            .map(|(k, &(i, _))| (k.clone(), i))
+                                            .collect();
+    for &(ref license, layer) in &names {
+        for &(ref other, other_layer) in &names {
+            if layer == other_layer {
+                continue;
+            }
+
+            let data = &mut merged.corpora.get_mut(license).unwrap().1;
+            let shared: Vec<usize> = (0..data.ngrams.len())
+                                         .filter(|&i| sets[other].contains(&data.ngrams[i]))
+                                         .collect();
+            if shared.is_empty() {
+                continue;
+            }
+
+            let resolved = if shared.len() < data.ngrams.len() {
+                let (size, aligned) = (data.ngrams.len(), data.offsets.len() == data.ngrams.len());
+                let keep = |i: &usize| !shared.contains(i);
+                if aligned {
+                    data.offsets = (0..data.offsets.len())
+                                       .filter(&keep)
+                                       .map(|i| data.offsets[i])
+                                       .collect();
+                }
+                data.ngrams = (0..data.ngrams.len())
+                                  .filter(&keep)
+                                  .map(|i| data.ngrams[i])
+                                  .collect();
+
+                if merged.texts.contains_key(license) {
+                    // Ngram `i` of the text starts at token `i`
+                    for (i, g) in ngrams[license].iter().enumerate() {
+                        if data.ngrams.len() >= size {
+                            break;
+                        }
+                        let unique = sets.iter().all(|(k, x)| *k == license || !x.contains(g));
+                        let stable = fixed.get(license).map_or(true, |x| x.contains(g));
+                        if unique && stable && !data.ngrams.contains(g) {
+                            data.ngrams.push(*g);
+                            if aligned {
+                                data.offsets.push(i);
+                            }
+                        }
+                    }
+                }
+                data.ngrams.len() >= header.unique_ngrams
+            } else if merged.texts.contains_key(license) {
+                // Discriminate by absence of the rest of the other text
+                let mut excludes: Vec<u64> = Vec::new();
+                for g in &ngrams[other] {
+                    if excludes.len() >= header.unique_ngrams {
+                        break;
+                    }
+                    if !sets[license].contains(g) && !excludes.contains(g) {
+                        excludes.push(*g);
+                    }
+                }
+                let resolved = !excludes.is_empty();
+                data.excludes.extend(excludes);
+                if resolved {
+                    data.subset_of.push(other.clone());
+                    data.subset_of.sort();
+                }
+                resolved
+            } else {
+                false
+            };
+
+            conflicts.push(Conflict {
+                kind: ConflictKind::Overlap,
+                detection: merged.kind,
+                license: license.clone(),
+                corpus: layers[layer].name.clone(),
+                other: other.clone(),
+                other_corpus: layers[other_layer].name.clone(),
+                resolved: resolved,
+            });
+        }
+    }
+}
+
+/// Combine corpora of `layers` into one, licenses defined by several
+/// layers are taken from the last of them. All layers need to be
+/// generated with the same ngram size and normalization, the header of
+/// the first one is used.
+pub fn combine(layers: Vec<Layer>) -> Result<Combined> {
+    let header = match layers.first() {
+        Some(x) => x.data.header.clone(),
+        None => return Err(LiceumError::Usage(String::from("No corpus to combine"))),
+    };
+    for layer in &layers[1..] {
+        let h = &layer.data.header;
+        if h.ngram_size != header.ngram_size || h.normalization != header.normalization {
+            return Err(LiceumError::Mismatch(format!("corpus {} was generated with other \
+                                                      ngram size or normalization than {}",
+                                                     layer.name,
+                                                     layers[0].name)));
+        }
+    }
+
+    let mut conflicts: Vec<Conflict> = Vec::new();
+    let mut licenses = merge(&layers,
+                             DetectionKind::Text,
+                             |l| (&l.data.licenses, &l.texts.licenses),
+                             &mut conflicts);
+    let mut notices = merge(&layers,
+                            DetectionKind::Notice,
+                            |l| (&l.data.notices, &l.texts.notices),
+                            &mut conflicts);
+    if layers.len() > 1 {
+        discriminate(&mut licenses, &layers, &header, &mut conflicts);
+        discriminate(&mut notices, &layers, &header, &mut conflicts);
+    }
+
+    // Hashes of overridden licenses are left out
+    let mut hashes: Vec<(String, String)> = Vec::new();
+    for (i, layer) in layers.iter().enumerate() {
+        hashes.extend(layer.hashes
+                           .iter()
+                           .filter(|x| licenses.corpora.get(&x.1).map_or(true, |c| c.0 == i))
+                           .cloned());
+    }
+
+    let mut origins: HashMap<(DetectionKind, String), String> = HashMap::new();
+    for merged in &[&licenses, &notices] {
+        for (name, &(i, _)) in &merged.corpora {
+            origins.insert((merged.kind, name.clone()), layers[i].name.clone());
+        }
+    }
+
+    let strip = |m: Merged| m.corpora.into_iter().map(|(k, v)| (k, v.1)).collect();
+    Ok(Combined {
+        texts: TextsData {
+            licenses: licenses.texts.clone(),
+            notices: notices.texts.clone(),
+        },
+        data: CorpusData {
+            header: header,
+            licenses: strip(licenses),
+            notices: strip(notices),
+        },
+        hashes: hashes,
+        origins: origins,
+        conflicts: conflicts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use corpus::{self, CorpusData, Header, LicenseText, TextsData, VecInData};
+    use detector::DetectionKind;
+    use error::LiceumError;
+
+    /// Text of `n` distinct words starting with `prefix`.
+    fn words(prefix: &str, n: usize) -> String {
+        (0..n).map(|i| format!("{}{}", prefix, i)).collect::<Vec<String>>().join(" ")
+    }
+
+    fn ngrams(text: &str) -> Vec<u64> {
+        let header = Header::default();
+        corpus::get_ngrams(text, header.ngram_size, &header.normalization)
+            .into_iter()
+            .map(|x| x.hash)
+            .collect()
+    }
+
+    /// Layer `name` of licenses given as (name, text, offsets of the
+    /// signature ngrams), with the texts unless `texts` is false.
+    fn layer(name: &str, licenses: &[(&str, &str, &[usize])], texts: bool) -> Layer {
+        let mut data = CorpusData {
+            header: Header::default(),
+            licenses: HashMap::new(),
+            notices: HashMap::new(),
+        };
+        let mut stored = TextsData::default();
+        for &(license, text, offsets) in licenses {
+            let all = ngrams(text);
+            data.licenses.insert(String::from(license),
+                                 VecInData {
+                                     ngrams: offsets.iter().map(|&i| all[i]).collect(),
+                                     level: 1,
+                                     excludes: Vec::new(),
+                                     subset_of: Vec::new(),
+                                     ssdeep_threshold: None,
+                                     meta: None,
+                                     vars: Vec::new(),
+                                     offsets: offsets.to_vec(),
+                                     tokens: all.len() + Header::default().ngram_size - 1,
+                                 });
+            if texts {
+                stored.licenses.insert(String::from(license),
+                                       LicenseText {
+                                           text: String::from(text),
+                                           template: None,
+                                           ngrams: Some(all),
+                                       });
+            }
+        }
+        Layer {
+            name: String::from(name),
+            hashes: licenses.iter()
+                            .map(|x| (format!("{}-{}", name, x.0), String::from(x.0)))
+                            .collect(),
+            data: data,
+            texts: stored,
+        }
+    }
+
+    fn overlaps(combined: &Combined) -> Vec<(String, bool)> {
+        combined.conflicts
+                .iter()
+                .filter(|x| x.kind == ConflictKind::Overlap)
+                .map(|x| (format!("{} {}", x.license, x.other), x.resolved))
+                .collect()
+    }
+
+    #[test]
+    fn later_duplicate_wins() {
+        let (a, b) = (words("alpha", 20), words("beta", 20));
+        let combined = combine(vec![layer("base", &[("A", &a, &[0, 1, 2])], true),
+                                    layer("internal", &[("A", &b, &[3, 4, 5])], true)])
+                           .unwrap();
+
+        assert_eq!(combined.data.licenses["A"].ngrams, ngrams(&b)[3..6].to_vec());
+        assert_eq!(combined.texts.licenses["A"].text, b);
+        assert_eq!(combined.hashes,
+                   vec![(String::from("internal-A"), String::from("A"))]);
+        assert_eq!(combined.conflicts,
+                   vec![Conflict {
+                            kind: ConflictKind::Duplicate,
+                            detection: DetectionKind::Text,
+                            license: String::from("A"),
+                            corpus: String::from("internal"),
+                            other: String::from("A"),
+                            other_corpus: String::from("base"),
+                            resolved: true,
+                        }]);
+    }
+
+    #[test]
+    fn replace_shared_ngrams() {
+        // B shares the first two signature ngrams of A
+        let a = words("alpha", 20);
+        let b = format!("{} {}", words("alpha", 8), words("beta", 20));
+        let combined = combine(vec![layer("base", &[("A", &a, &[0, 1, 2])], true),
+                                    layer("internal", &[("B", &b, &[10, 11, 12])], true)])
+                           .unwrap();
+
+        let signature = &combined.data.licenses["A"];
+        assert_eq!(signature.ngrams, ngrams(&a)[2..5].to_vec());
+        assert_eq!(signature.offsets, vec![2, 3, 4]);
+        assert!(signature.excludes.is_empty());
+        assert_eq!(overlaps(&combined), vec![(String::from("A B"), true)]);
+    }
+
+    #[test]
+    fn exclude_containing_text() {
+        // B contains the whole text of A
+        let a = words("alpha", 20);
+        let b = format!("{} {}", a, words("beta", 20));
+        let combined = combine(vec![layer("base", &[("A", &a, &[0, 1, 2])], true),
+                                    layer("internal", &[("B", &b, &[25, 26, 27])], true)])
+                           .unwrap();
+
+        let signature = &combined.data.licenses["A"];
+        assert_eq!(signature.ngrams, ngrams(&a)[0..3].to_vec());
+        assert_eq!(signature.excludes, ngrams(&b)[14..17].to_vec());
+        assert_eq!(signature.subset_of, vec![String::from("B")]);
+        assert_eq!(overlaps(&combined), vec![(String::from("A B"), true)]);
+    }
+
+    #[test]
+    fn unresolved_without_text() {
+        let a = words("alpha", 20);
+        let b = format!("{} {}", words("alpha", 8), words("beta", 20));
+        let combined = combine(vec![layer("base", &[("A", &a, &[0, 1, 2])], false),
+                                    layer("internal", &[("B", &b, &[10, 11, 12])], true)])
+                           .unwrap();
+
+        assert_eq!(combined.data.licenses["A"].ngrams, ngrams(&a)[2..3].to_vec());
+        assert_eq!(overlaps(&combined), vec![(String::from("A B"), false)]);
+    }
+
+    #[test]
+    fn attribute_licenses_to_layers() {
+        let (a, b) = (words("alpha", 20), words("beta", 20));
+        let combined = combine(vec![layer("base", &[("A", &a, &[0, 1, 2])], true),
+                                    layer("internal", &[("B", &b, &[0, 1, 2])], true)])
+                           .unwrap();
+
+        assert!(combined.conflicts.is_empty());
+        let origin = |x: &str| combined.origins[&(DetectionKind::Text, String::from(x))].clone();
+        assert_eq!(origin("A"), "base");
+        assert_eq!(origin("B"), "internal");
+    }
+
+    #[test]
+    fn reject_other_ngram_size() {
+        let mut other = layer("internal", &[("B", &words("beta", 20), &[0, 1, 2])], true);
+        other.data.header.ngram_size = 5;
+        match combine(vec![layer("base", &[("A", &words("alpha", 20), &[0, 1, 2])], true), other]) {
+            Err(LiceumError::Mismatch(_)) => {}
+            x => panic!("expected a mismatch, got {:?}", x.map(|c| c.conflicts)),
+        }
+    }
+}
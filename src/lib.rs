@@ -0,0 +1,54 @@
+//! Liceum - advanced license detector
+//!
+//! Licenses are recognized by two independent methods, unique ngrams
+//! extracted from the license corpus and ssdeep fuzzy hashes. Both are
+//! available through the `Detector` type, which also reads
+//! `SPDX-License-Identifier` tags declared by the scanned files.
+extern crate regex;
+extern crate rustc_serialize;
+extern crate threadpool;
+extern crate csv;
+extern crate walkdir;
+extern crate sha1;
+extern crate time;
+extern crate memmap;
+
+pub mod pathex;
+pub mod ngram;
+pub mod diff;
+pub mod ssdeep;
+pub mod sniff;
+pub mod normalize;
+pub mod corpus;
+pub mod index;
+pub mod binary;
+pub mod embedded;
+pub mod layers;
+pub mod fusion;
+pub mod detector;
+pub mod config;
+pub mod policy;
+pub mod spdx;
+pub mod output;
+pub mod error;
+mod util;
+
+pub use detector::{Change, ChangeKind, Detector, Detection, DetectionKind, Explanation,
+                   FileError, FileResult, LicenseTag, NgramMatch, ScanResult, Segment, Span};
+pub use config::Config;
+pub use corpus::Header;
+pub use fusion::Fusion;
+pub use layers::{Conflict, ConflictKind};
+pub use error::{ErrorKind, LiceumError, Result};
+pub use normalize::Normalization;
+pub use policy::{Policy, Verdict};
+
+/// Name of the ssdeep hashes file inside a data directory.
+pub static SSDEEP_HASHES: &'static str = "hashes.ssdeep";
+/// Name of the ngram corpus file inside a data directory.
+pub static NGRAMS_FILE: &'static str = "ngrams.json";
+/// Name of the binary ngram corpus file inside a data directory, it is
+/// loaded in place of `NGRAMS_FILE` when present.
+pub static BINARY_FILE: &'static str = "ngrams.bin";
+/// Name of the file with the license texts inside a data directory.
+pub static TEXTS_FILE: &'static str = "texts.json";
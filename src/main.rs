@@ -1,476 +1,521 @@
-extern crate regex;
+extern crate liceum;
 extern crate rustc_serialize;
 extern crate getopts;
-extern crate threadpool;
-extern crate csv;
-extern crate walkdir;
-
-use std::fs::File;
-use std::io::{Read, Error, Write};
-use std::iter::FromIterator;
-use std::collections::{HashSet, HashMap};
-use std::fmt::Debug;
-use std::{fs, fmt, env};
-use std::path::Path;
-use std::sync::{Arc, mpsc};
-use std::hash::Hash;
-use std::process::Command;
-use std::collections::hash_map::Entry::{Occupied, Vacant};
-
-use getopts::Options;
-use rustc_serialize::json;
-use threadpool::ThreadPool;
-use regex::Regex;
-use walkdir::{DirEntry, WalkDir, WalkDirIterator};
-
-pub mod pathex;
-pub mod ngram;
-pub mod ssdeep;
-use ngram::NGram;
-use pathex::AbsolutePath;
-
-static SSDEEP_HASHES: &'static str = "hashes.ssdeep";
-static NGRAMS_FILE: &'static str = "ngrams.json";
-
-/// Insert multiple values under a single key
-trait OneToMany<K, V> {
-    fn insert_one(&mut self, key: K, value: V);
-}
-
-impl<K: Eq + Hash, V> OneToMany<K, V> for HashMap<K, Vec<V>> {
-    /// If `key` is not present insert the key with a vector containing the `value`
-    /// whereas if `key` is already present then push the `value` into the vector
-    fn insert_one(&mut self, key: K, value: V) {
-        match self.entry(key) {
-            Vacant(entry) => {
-                entry.insert(vec![value]);
-            }
-            Occupied(mut entry) => entry.get_mut().push(value),
-        };
-    }
-}
-
-type IoResult<T> = Result<T, Error>;
-
-/// Generic container for `leveled ngrams`
-#[derive(RustcDecodable, RustcEncodable, Debug)]
-struct Data<T> {
-    ngrams: Vec<T>,
-    level: u64,
-}
 
-/// Generic string ngram
-type NG = NGram<String>;
+use std::collections::HashMap;
+use std::io::Write;
+use std::str::FromStr;
+use std::{fs, env};
 
-/// Generic vector owning it's ngrams
-type NGramVec = Vec<NG>;
-
-/// Raw output and input data for processing
-type OutData<'a> = Data<&'a NG>;
-type InData = Data<NG>;
-
-/// Primitive representation of the output JSON document
-type VecOutData<'a> = Data<&'a Vec<String>>;
-type JsonOutMap<'a> = HashMap<&'a str, VecOutData<'a>>;
+use getopts::{Matches, Options};
+use rustc_serialize::json;
 
-/// Primitive representation of the input JSON document
-type VecInData = Data<Vec<String>>;
-type JsonInMap = HashMap<String, VecInData>;
+use liceum::{Change, Config, Conflict, ConflictKind, Detection, Detector, Explanation, Fusion,
+             Header, LiceumError, LicenseTag, NgramMatch, Normalization, Policy, ScanResult,
+             Segment, Span};
+use liceum::binary::{self, Format};
+use liceum::output::{cyclonedx, sarif, spdx};
 
-/// Input corpus structure holding basic information
-struct InputCorpus {
+/// Error entry of the output JSON document
+#[derive(RustcEncodable)]
+struct ErrorOut {
     file: String,
-    data: InData,
+    kind: String,
+    message: String,
 }
 
-/// All input corpuses
-type InputVector = Vec<InputCorpus>;
-
-/// Store information about licenses found per file
-struct SearchResult {
-    file: String,
-    found: String,
+/// Ngram evidence of the output JSON document
+#[derive(RustcEncodable)]
+struct NgramOut {
+    found: usize,
+    total: usize,
+    coverage: f64,
+    level: u64,
 }
 
-/// Maps corpus name to vector of ngrams.
-#[derive(Eq, PartialEq, Hash)]
-struct LicenseCorpus {
-    file: String,
-    ngrams: NGramVec,
+/// Part of a scanned file in the output JSON document
+#[derive(RustcEncodable)]
+struct SpanOut {
+    start_line: usize,
+    end_line: usize,
+    start_byte: usize,
+    end_byte: usize,
 }
 
-impl Debug for LicenseCorpus {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f,
-               "LicenseCorpus: {} ({} ngrams)",
-               self.file,
-               self.ngrams.len())
+impl SpanOut {
+    fn new(s: &Span) -> SpanOut {
+        SpanOut {
+            start_line: s.start_line,
+            end_line: s.end_line,
+            start_byte: s.start_byte,
+            end_byte: s.end_byte,
+        }
     }
 }
 
-fn read_file(file: &str) -> IoResult<String> {
-    let mut f = try!(File::open(file));
-    let mut s = String::new();
-    try!(f.read_to_string(&mut s));
-    Ok(s)
+impl NgramOut {
+    fn new(m: &NgramMatch) -> NgramOut {
+        NgramOut {
+            found: m.found,
+            total: m.total,
+            coverage: m.coverage(),
+            level: m.level,
+        }
+    }
 }
 
-fn write_file(file: &str, data: &str) -> IoResult<usize> {
-    let mut f = try!(File::create(file));
-    let written = try!(f.write(data.as_bytes()));
-    // println!(" {} bytes written", written);
-    Ok(written)
+/// License entry of the output JSON document
+#[derive(RustcEncodable)]
+struct LicenseOut {
+    license: String,
+    kind: String,
+    confidence: f64,
+    ngram: Option<NgramOut>,
+    ssdeep: Option<u32>,
+    region: SpanOut,
+    file_coverage: f64,
+    matches: Vec<SpanOut>,
+    corpus: Option<String>,
 }
 
-const NGRAM_SIZE: usize = 7;
-
-/// Get ngrams of size `n` from input string `from`.
-fn get_ngrams(from: &str, n: usize) -> NGramVec {
-    let minus_newlines = from.replace("\n", " ");
-
-    // Collapse 2+ consecutive whitespace characters into one
-    let whitespace_collapser = Regex::new(r"[\r\t\v ]{1,}").unwrap();
-    // Remove <text_in_angle_brackets>
-    let angle_remover = Regex::new(r"<[\w_]*>").unwrap();
-    // Remove ____...
-    let underscore_filter = Regex::new(r"_{2,}").unwrap();
-    let sanitized = whitespace_collapser.replace_all(
-        &angle_remover.replace_all(
-            &underscore_filter.replace_all(&minus_newlines, ""), 
-            ""), 
-        " ");
-    // Get rid of all empty chars
-    let filtered = sanitized.split(' ')
-                            .filter(|x| *x != "")
-                            .collect::<Vec<&str>>()
-                            .join(" ");
-    // Create `n` iterators splitting the string by the space character
-    let mut iterators = vec![filtered.split(' '); n];
-
-    // Skew the iterators by their position such as:
-    //
-    // I.   | ABCDEFGHIJKL
-    // II.  |  BCDEFGHIJKL
-    // III. |   CDEFGHIJKL
-    //  ...
-    //
-    // Reading from each iterator will then yield next n-gram
-    let mut cnt = 0;
-    for iterator in &mut iterators {
-        for _ in 0..cnt {
-            let n = iterator.next()
-                            .map_or(false, |_| true);
-            if !n {
-                return Vec::new();
-            }
+impl LicenseOut {
+    fn new(d: &Detection) -> LicenseOut {
+        LicenseOut {
+            license: d.license.clone(),
+            kind: d.kind.to_string(),
+            confidence: d.confidence,
+            ngram: d.ngram.as_ref().map(NgramOut::new),
+            ssdeep: d.ssdeep,
+            region: SpanOut::new(&d.region),
+            file_coverage: d.file_coverage,
+            matches: d.matches.iter().map(SpanOut::new).collect(),
+            corpus: d.corpus.clone(),
         }
-
-        cnt += 1;
     }
+}
 
-    let mut grams: NGramVec = Vec::new();
-    let mut ng: Vec<&str> = Vec::new();
-    'main: loop {
-        for iterator in &mut iterators {
-            if let Some(item) = iterator.next() {
-                ng.push(item);
-            } else {
-                // one of the iterators has reached the end, break out of the main loop
-                break 'main;
-            }
-        }
+/// Segment of a file in the output JSON document
+#[derive(RustcEncodable)]
+struct SegmentOut {
+    license: Option<String>,
+    kind: String,
+    confidence: f64,
+    ngram: Option<NgramOut>,
+    ssdeep: Option<u32>,
+    region: SpanOut,
+    corpus: Option<String>,
+}
 
-        let values = ng.iter()
-                       .map(|x| String::from(*x))
-                       .collect::<Vec<_>>();
-        let ngram = NGram::new(&values);
-        grams.push(ngram);
-        ng.clear();
+impl SegmentOut {
+    fn new(s: &Segment) -> SegmentOut {
+        SegmentOut {
+            license: s.license.clone(),
+            kind: s.kind.to_string(),
+            confidence: s.confidence,
+            ngram: s.ngram.as_ref().map(NgramOut::new),
+            ssdeep: s.ssdeep,
+            region: SpanOut::new(&s.region),
+            corpus: s.corpus.clone(),
+        }
     }
-
-    grams
 }
 
-/// Save data from the input map into a `JsonOutMap` for JSON serialization.
-fn save_data<'a>(data: &'a HashMap<&'a LicenseCorpus, OutData>) -> JsonOutMap<'a> {
-    let mut out: JsonOutMap = HashMap::new();
-
-    for (corpus, ngrams) in data {
-        let name = Path::new(&corpus.file)
-                       .file_stem()
-                       .unwrap()
-                       .to_str()
-                       .unwrap();
+/// Difference from a license text in the output JSON document
+#[derive(RustcEncodable)]
+struct ChangeOut {
+    kind: String,
+    expected: String,
+    found: String,
+    region: SpanOut,
+    variable: bool,
+}
 
-        let mut data_grams: Vec<&Vec<String>> = Vec::new();
-        for g in &ngrams.ngrams {
-            data_grams.push(&g.elements.obj);
+impl ChangeOut {
+    fn new(c: &Change) -> ChangeOut {
+        ChangeOut {
+            kind: c.kind.to_string(),
+            expected: c.expected.clone(),
+            found: c.found.clone(),
+            region: SpanOut::new(&c.region),
+            variable: c.variable,
         }
-
-        let data = VecOutData {
-            level: ngrams.level,
-            ngrams: data_grams,
-        };
-
-        out.insert(name, data);
     }
-
-    out
 }
 
-/// Creates `Arc<InputVector>` sorted by data level from
-/// input license corpus.
-fn load_data(input: &JsonInMap) -> Arc<InputVector> {
-    let mut licenses: InputVector = Vec::new();
+/// Alignment against a license text in the output JSON document
+#[derive(RustcEncodable)]
+struct ExplanationOut {
+    license: String,
+    kind: String,
+    region: SpanOut,
+    matching: usize,
+    total: usize,
+    modified: bool,
+    changes: Vec<ChangeOut>,
+}
 
-    for (k, v) in input {
-        let mut ngrams: NGramVec = Vec::new();
-        for ngram in &v.ngrams {
-            ngrams.push(NGram::new(ngram));
+impl ExplanationOut {
+    fn new(e: &Explanation) -> ExplanationOut {
+        ExplanationOut {
+            license: e.license.clone(),
+            kind: e.kind.to_string(),
+            region: SpanOut::new(&e.region),
+            matching: e.matching,
+            total: e.total,
+            modified: e.is_modified(),
+            changes: e.changes.iter().map(ChangeOut::new).collect(),
         }
-
-        let data = InData {
-            level: v.level,
-            ngrams: ngrams,
-        };
-
-        let item = InputCorpus {
-            file: k.clone(),
-            data: data,
-        };
-
-        licenses.push(item);
     }
+}
 
-    licenses.sort_by_key(|x| x.data.level);
-
-    Arc::new(licenses)
+/// Conflict between combined corpora in the output JSON document
+#[derive(RustcEncodable)]
+struct ConflictOut {
+    kind: String,
+    detection: String,
+    license: String,
+    corpus: String,
+    other: String,
+    other_corpus: String,
+    resolved: bool,
 }
 
-/// Generate ngram corpuses from all files in `data_dir` and return them
-/// as a string serialized JSON.
-fn generate_corpuses(data_dir: &str, verbose: bool) -> String {
-    // Load corpus files
-    let mut corpuses: Vec<LicenseCorpus> = Vec::new();
-    let paths = fs::read_dir(data_dir).unwrap();
-    for path in paths {
-        let p = path.unwrap().path();
-        if verbose {
-            println!("{:?}", p);
+impl ConflictOut {
+    fn new(c: &Conflict) -> ConflictOut {
+        ConflictOut {
+            kind: c.kind.to_string(),
+            detection: c.detection.to_string(),
+            license: c.license.clone(),
+            corpus: c.corpus.clone(),
+            other: c.other.clone(),
+            other_corpus: c.other_corpus.clone(),
+            resolved: c.resolved,
         }
+    }
+}
 
-        let data = read_file(p.to_str().unwrap()).unwrap();
-        let ngrams = get_ngrams(&data, NGRAM_SIZE);
+/// `SPDX-License-Identifier` tag of the output JSON document
+#[derive(RustcEncodable)]
+struct TagOut {
+    line: usize,
+    value: String,
+    expression: Option<String>,
+    licenses: Vec<String>,
+    error: Option<String>,
+    unknown: Vec<String>,
+    deprecated: Vec<String>,
+}
 
-        corpuses.push(LicenseCorpus {
-            file: String::from(p.to_str().unwrap()),
-            ngrams: ngrams,
+impl TagOut {
+    fn new(t: &LicenseTag) -> TagOut {
+        let licenses = t.expression.as_ref().map_or(Vec::new(), |x| {
+            x.licenses().iter().map(|l| l.to_string()).collect()
         });
+        TagOut {
+            line: t.line,
+            value: t.value.clone(),
+            expression: t.expression.as_ref().map(|x| x.to_string()),
+            licenses: licenses,
+            error: t.error.clone(),
+            unknown: t.unknown.clone(),
+            deprecated: t.deprecated.clone(),
+        }
     }
+}
+
+/// Settings the search ran with, part of the output JSON document
+#[derive(RustcEncodable)]
+struct MetadataOut {
+    corpora: Vec<String>,
+    ngram_size: usize,
+    min_confidence: f64,
+    ssdeep_threshold: u32,
+    license_thresholds: HashMap<String, u32>,
+    fusion: String,
+    validate_vars: bool,
+    detect_notices: bool,
+    segment: bool,
+    explain: bool,
+}
 
-    let mut ngrammap: HashMap<&NGram<String>, Vec<&LicenseCorpus>> = HashMap::new();
-    if verbose {
-        println!("[+] Generating n-gram map for {} corpuses", corpuses.len());
+impl MetadataOut {
+    fn new(detector: &Detector) -> MetadataOut {
+        MetadataOut {
+            corpora: detector.corpora().to_vec(),
+            ngram_size: detector.header().ngram_size,
+            min_confidence: detector.min_confidence(),
+            ssdeep_threshold: detector.ssdeep_threshold(),
+            license_thresholds: detector.license_thresholds().clone(),
+            fusion: detector.fusion().to_string(),
+            validate_vars: detector.validate_vars(),
+            detect_notices: detector.detect_notices(),
+            segment: detector.segment(),
+            explain: detector.explain(),
+        }
     }
+}
 
-    {
-        let z = &corpuses;
-        for corpus in z {
-            for ngram in &corpus.ngrams {
-                ngrammap.insert_one(ngram, corpus);
-            }
+/// Output JSON document
+#[derive(RustcEncodable)]
+struct Output {
+    metadata: MetadataOut,
+    // file: vec![found_licenses]
+    files: HashMap<String, Vec<LicenseOut>>,
+    // file: vec![SPDX-License-Identifier tags]
+    tags: HashMap<String, Vec<TagOut>>,
+    // file: vec![segments in order], with --segment
+    segments: HashMap<String, Vec<SegmentOut>>,
+    // file: vec![differences from the licenses found], with --explain
+    explanations: HashMap<String, Vec<ExplanationOut>>,
+    // licenses of the combined corpora conflicting with each other
+    conflicts: Vec<ConflictOut>,
+    errors: Vec<ErrorOut>,
+}
+
+/// Names of the supported output formats
+static FORMATS: &'static [&'static str] = &["json",
+                                            "spdx",
+                                            "spdx-json",
+                                            "cyclonedx",
+                                            "cyclonedx-xml",
+                                            "sarif"];
+
+/// Format `result` as the native JSON document.
+fn native_output(detector: &Detector, result: ScanResult) -> String {
+    let mut output = Output {
+        metadata: MetadataOut::new(detector),
+        files: HashMap::new(),
+        tags: HashMap::new(),
+        segments: HashMap::new(),
+        explanations: HashMap::new(),
+        conflicts: detector.conflicts().iter().map(ConflictOut::new).collect(),
+        errors: Vec::new(),
+    };
+    for r in result.files {
+        if !r.tags.is_empty() {
+            output.tags.insert(r.file.clone(), r.tags.iter().map(TagOut::new).collect());
+        }
+        if !r.segments.is_empty() {
+            let segments = r.segments.iter().map(SegmentOut::new).collect();
+            output.segments.insert(r.file.clone(), segments);
+        }
+        if !r.explanations.is_empty() {
+            let explanations = r.explanations.iter().map(ExplanationOut::new).collect();
+            output.explanations.insert(r.file.clone(), explanations);
+        }
+        if !r.detections.is_empty() {
+            let found = r.detections.iter().map(LicenseOut::new).collect();
+            output.files.insert(r.file, found);
         }
     }
-    if verbose {
-        println!("[!] Done generating n-gram map: {} items", ngrammap.len());
+    for e in result.errors {
+        output.errors.push(ErrorOut {
+            file: e.file,
+            kind: e.kind.to_string(),
+            message: e.message,
+        });
     }
 
-    // Map corpuses to unique ngrams
-    let mut fm: HashMap<&LicenseCorpus, OutData> = HashMap::new();
+    let pj = json::as_pretty_json(&output).indent(3);
+    format!("{}", pj)
+}
 
-    // Store finished corpuses
-    let mut finished: HashSet<&LicenseCorpus> = HashSet::new();
+fn print_usage(code: i32, program: &str, opts: &Options) {
+    let banner = format!("Usage: {} [options] PATH", program);
+    println!("{} - {}", program, env!("CARGO_PKG_VERSION"));
+    print!("{}", opts.usage(&banner));
+    std::process::exit(code);
+}
 
-    // We'll mutate this hashmap at the end of each loop to remove
-    // garbage ngrams (ngrams unique to already finished corpus)
-    let mut allngrams = ngrammap.clone();
+/// Parse numeric value of option `name`, if present.
+fn opt_num<T: FromStr>(matches: &Matches, name: &str) -> liceum::Result<Option<T>> {
+    match matches.opt_str(name) {
+        Some(x) => {
+            x.parse::<T>()
+             .map(Some)
+             .map_err(|_| LiceumError::Usage(format!("Invalid number for -{}: {}", name, x)))
+        }
+        None => Ok(None),
+    }
+}
 
-    let pgbar: [&str; 4] = ["-", "\\", "|", "/"];
-    let (mut loops, mut prints) = (1, 0);
+fn run(matches: &Matches) -> liceum::Result<()> {
+    let verbose = matches.opt_present("v");
+    let check_data = matches.opt_strs("c");
+    let is_check = !check_data.is_empty();
+    let is_generate = matches.opt_present("g");
+    if is_generate && is_check {
+        return Err(LiceumError::Usage(String::from("Options -g and -c are mutually exclusive")));
+    } else if !is_generate && !is_check && !liceum::embedded::AVAILABLE {
+        return Err(LiceumError::Usage(String::from("Provide either -g or -c argument")));
+    }
 
-    loop {
-        if finished.len() == corpuses.len() {
-            break;
+    let gen_data = match matches.opt_str("g") {
+        Some(x) => x,
+        None => String::new(),
+    };
+    let ngram_size = try!(opt_num::<usize>(matches, "n"));
+    let unique_ngrams = try!(opt_num::<usize>(matches, "u"));
+    let min_confidence = try!(opt_num::<f64>(matches, "m"));
+    let ssdeep_threshold = try!(opt_num::<u32>(matches, "s"));
+    let normalization = match matches.opt_str("N") {
+        Some(x) => Some(try!(Normalization::parse(&x).map_err(LiceumError::Usage))),
+        None => None,
+    };
+    let config = match matches.opt_str("C") {
+        Some(x) => try!(Config::load(&x)),
+        None => Config::default(),
+    };
+
+    // without -g and -c files are checked using the embedded corpus
+    if !is_generate {
+        if check_data.iter().any(|x| x.is_empty()) {
+            return Err(LiceumError::Usage(String::from("Empty check data")));
         }
 
-        let last = finished.len();
-        let count = allngrams.len();
-        let mut cleanup: Vec<&NGram<String>> = Vec::new();
-        for (i, (ngram, mut occurences)) in allngrams.iter_mut().enumerate() {
-            if i % 100 == 0 {
-                print!("\r[{}] Processing .. {}/{}", pgbar[prints % 4], i, count);
-                std::io::stdout().flush().ok();
-                prints += 1;
+        if let Some(x) = matches.opt_str("convert") {
+            if !is_check {
+                return Err(LiceumError::Usage(String::from("Option --convert is only used \
+                                                            with -c")));
             }
-
-            // We have an ngram with only a single edge
-            if occurences.len() == 1 {
-                let key = occurences.iter().next().unwrap();
-                if finished.contains(key) {
-                    cleanup.push(ngram);
-                    continue;
-                }
-
-                if fm.contains_key(key) {
-                    let mut ngrams = fm.get_mut(key).unwrap();
-                    ngrams.ngrams.push(ngram);
-                    ngrams.level = loops;
-                    // 3 unique ngrams
-                    if ngrams.ngrams.len() > 2 {
-                        if verbose {
-                            println!("\r finished: {:?}", key.file);
-                        }
-                        finished.insert(key);
-                        cleanup.push(ngram);
-                    }
-                } else {
-                    fm.insert(key,
-                              OutData {
-                                  ngrams: vec![ngram],
-                                  level: loops,
-                              });
-                    cleanup.push(ngram);
-                }
-            } else {
-                for fin in &finished {
-                    if let Some(x) = occurences.iter().position(|x| x == fin) {
-                        occurences.remove(x);
-                    };
-                }
+            if check_data.len() > 1 {
+                return Err(LiceumError::Usage(String::from("Option --convert takes a single \
+                                                            -c")));
             }
+            let format = try!(Format::parse(&x).map_err(LiceumError::Usage));
+            return binary::convert(&check_data[0], format);
         }
 
-        for ng in cleanup {
-            allngrams.remove(ng);
+        if matches.free.is_empty() {
+            return Err(LiceumError::Usage(String::from("Nothing to check")));
         }
-
-        if last == finished.len() {
-            println!("\nRemaining: {}", corpuses.len() - finished.len());
-            for key in &corpuses {
-                if !finished.contains(key) {
-                    println!("\n {}", key.file);
-                }
-            }
-            // We didn't find a single solution during this iteration
-            // so we bail out, some further graph magic will be needed
-            // to find the solution (if there is one).
-            panic!("[E] Solution not found! ¯\\_(ツ)_/¯ ");
+        if matches.free.len() > 1 {
+            return Err(LiceumError::Usage(String::from("Only a single path is checked at a \
+                                                        time")));
         }
 
-        loops += 1;
-    }
-
-    println!("{} corpuses created!", finished.len());
-
-    let result: JsonOutMap = save_data(&fm);
-    // Format as pretty JSON and write to file
-    let pj = json::as_pretty_json(&result).indent(3);
-    format!("{}", &pj)
-}
-
-/// Predicate determining whether the path is hidden.
-fn is_hidden(entry: &DirEntry) -> bool {
-    entry.path()
-         .to_str()
-         .map_or(false, |s| s.contains("/."))
-}
-
-/// Predicate determining whether the path is a text file or directory
-/// entry. Due to the recursive nature of the walker we can't filter out
-/// directories here.
-fn is_text_or_dirent(entry: &DirEntry) -> bool {
-    let result = match Command::new("file")
-                           .arg("-bi")
-                           .arg(entry.path().to_str().unwrap())
-                           .output() {
-        Ok(output) => String::from_utf8_lossy(&output.stdout).into_owned(),
-        Err(_) => String::new(),
-    };
-
-    result.starts_with("text/") || result.starts_with("inode/directory;")
-}
+        if unique_ngrams.is_some() {
+            return Err(LiceumError::Usage(String::from("Option -u is only used with -g")));
+        }
+        if matches.opt_present("notices") {
+            return Err(LiceumError::Usage(String::from("Option --notices is only used with \
+                                                        -g")));
+        }
 
-/// Search `path` using data from `data` for both ngrams and ssdeep hashes.
-/// Returns a string serialized JSON.
-fn search_path(data: &str, path: &str) -> String {
-    let pool = ThreadPool::new(16);
-    let mut paths: Vec<String> = Vec::new();
+        let mut detector = if is_check {
+            try!(Detector::load_layers(&check_data))
+        } else {
+            try!(Detector::embedded())
+        };
+        // overlaps the combined corpora tell apart are only listed with -v
+        for c in detector.conflicts() {
+            if verbose || !c.resolved || c.kind == ConflictKind::Duplicate {
+                writeln!(&mut std::io::stderr(), "[W] {}", c).ok();
+            }
+        }
+        if let Some(n) = ngram_size {
+            try!(detector.require_ngram_size(n));
+        }
+        // all combined corpora share the normalization of the first one
+        if let Some(ref x) = normalization {
+            try!(detector.require_normalization(x));
+        }
+        // command line options take precedence over the config file
+        try!(config.apply(&mut detector));
+        if let Some(x) = min_confidence {
+            detector.set_min_confidence(x);
+        }
+        if let Some(x) = ssdeep_threshold {
+            try!(detector.set_ssdeep_threshold(x));
+        }
+        if let Some(x) = matches.opt_str("f") {
+            detector.set_fusion(try!(Fusion::parse(&x).map_err(LiceumError::Usage)));
+        }
+        if matches.opt_present("validate-vars") {
+            detector.set_validate_vars(true);
+        }
+        if matches.opt_present("no-notices") {
+            detector.set_detect_notices(false);
+        }
+        if matches.opt_present("segment") {
+            detector.set_segment(true);
+        }
+        if matches.opt_present("explain") {
+            detector.set_explain(true);
+        }
+        if detector.min_confidence() < 0.0 || detector.min_confidence() > 1.0 {
+            return Err(LiceumError::Usage(String::from("Minimal confidence must be between 0 \
+                                                        and 1")));
+        }
+        let format = matches.opt_str("o").unwrap_or_else(|| String::from("json"));
+        if !FORMATS.contains(&format.as_str()) {
+            return Err(LiceumError::Usage(format!("Unknown output format: {}", format)));
+        }
 
-    let walker = WalkDir::new(path).into_iter();
-    for p in walker.filter_entry(|e| !is_hidden(e) && is_text_or_dirent(e)) {
-        let file_entry = p.unwrap();
-        let file = file_entry.path().to_str().unwrap();
+        let mut policy = config.policy();
+        if let Some(x) = matches.opt_str("allow") {
+            policy.allowed = Policy::parse_list(&x);
+        }
+        if let Some(x) = matches.opt_str("deny") {
+            policy.denied = Policy::parse_list(&x);
+        }
 
-        if file_entry.file_type().is_file() {
-            paths.push(String::from(file));
+        let root = &matches.free[0];
+        let result = detector.scan_path(root);
+        match format.as_str() {
+            "spdx" => {
+                print!("{}",
+                       spdx::Document::new(&result, root, detector.texts()).to_tag_value())
+            }
+            "spdx-json" => {
+                println!("{}", spdx::Document::new(&result, root, detector.texts()).to_json())
+            }
+            "cyclonedx" => println!("{}", cyclonedx::Bom::new(&result, root).to_json()),
+            "cyclonedx-xml" => print!("{}", cyclonedx::Bom::new(&result, root).to_xml()),
+            "sarif" => println!("{}", sarif::Log::new(&result, root, &policy).to_json()),
+            _ => println!("{}", native_output(&detector, result)),
+        }
+    } else {
+        if matches.opt_present("convert") {
+            return Err(LiceumError::Usage(String::from("Option --convert is only used with \
+                                                        -c")));
+        }
+        for name in &["s", "m", "f"] {
+            if matches.opt_present(name) {
+                return Err(LiceumError::Usage(format!("Option -{} is only used with -c", name)));
+            }
+        }
+        if gen_data == "" {
+            return Err(LiceumError::Usage(String::from("No target directory from which to \
+                                                        generate data")));
         }
-    }
 
-    let rx = {
-        let (tx, rx) = mpsc::channel();
-        let ngrams_path = Path::new(data).join(NGRAMS_FILE);
-        let d = read_file(ngrams_path.to_str().unwrap()).unwrap();
-        let decoded: JsonInMap = json::decode(&d).unwrap();
-        let licenses = load_data(&decoded);
-
-        for p in paths.iter().cloned() {
-            let (tx, licenses) = (tx.clone(), licenses.clone());
-
-            pool.execute(move || {
-                let ng = get_ngrams(&read_file(&p).unwrap(), NGRAM_SIZE);
-                let ngrams: HashSet<&NGram<String>> = HashSet::from_iter(ng.iter());
-
-                for ref ic in licenses.iter() {
-                    let check = ic.data.ngrams.iter().all(|x| ngrams.contains(x));
-                    if check {
-                        tx.send(Arc::new(SearchResult {
-                              file: p.clone(),
-                              found: ic.file.clone(),
-                          }))
-                          .unwrap();
-                    }
-                }
-            });
-        }
-
-        rx
-    };
+        let mut header = Header::default();
+        if let Some(x) = normalization {
+            header.normalization = x;
+        }
+        header.ngram_size = ngram_size.unwrap_or(header.ngram_size);
+        header.unique_ngrams = unique_ngrams.unwrap_or(header.unique_ngrams);
+        try!(header.validate());
 
-    // file: vec![found_licenses]
-    let mut results: HashMap<String, Vec<String>> = HashMap::new();
-    while let Ok(item) = rx.recv() {
-        let p = Path::new(&item.file).canonical_path();
-        results.insert_one(String::from(p.as_path().to_str().unwrap()),
-                           item.found.clone());
-    }
+        fs::create_dir("cache/").ok();
 
-    // ssdeep search
-    let hashes_path = Path::new(data).join(SSDEEP_HASHES);
-    // TODO: Make sensitivity tunable
-    let hashed = ssdeep::compare(hashes_path.to_str().unwrap(), path, 75);
-    for h in &hashed {
-        results.insert_one(h.file_a.clone(), h.file_b.clone());
+        // per-license ssdeep thresholds from the config file are stored in
+        // the corpus
+        let notices = matches.opt_str("notices");
+        let mut detector = try!(Detector::generate(&gen_data,
+                                                   notices.as_ref().map(|x| x.as_str()),
+                                                   &header,
+                                                   verbose));
+        try!(config.apply(&mut detector));
+        try!(detector.save("cache/"));
+        writeln!(&mut std::io::stderr(),
+                 "{} corpuses created!",
+                 detector.licenses().len())
+            .ok();
     }
 
-    let pj = json::as_pretty_json(&results).indent(3);
-    format!("{}", pj)
-}
-
-fn print_usage(code: i32, program: &str, opts: &Options) {
-    let banner = format!("Usage: {} [options] ...", program);
-    println!("{} - {}", program, "0.1.0");
-    print!("{}", opts.usage(&banner));
-    std::process::exit(code);
+    Ok(())
 }
 
 fn main() {
@@ -482,63 +527,108 @@ fn main() {
                 "generate",
                 "generate data from target directory",
                 "DIR");
-    opts.optopt("c", "check", "check using this data corpus", "FILE");
+    opts.optmulti("c",
+                  "check",
+                  "check using this data corpus, repeat to combine several corpora with \
+                   later ones taking precedence, with the embedded-corpus feature it \
+                   overrides the embedded one",
+                  "DIR");
+    opts.optopt("",
+                "notices",
+                "with -g also generate license notices from this directory, one notice per \
+                 file named after the license",
+                "DIR");
+    opts.optopt("N",
+                "normalize",
+                "normalization steps used for generation: case, punctuation, bullets, words, \
+                 copyright, all (default) or none, with -c the corpus is required to use \
+                 these steps",
+                "STEPS");
+    opts.optopt("n",
+                "ngram-size",
+                "number of tokens per ngram used for generation (default 7), with -c the \
+                 corpus is required to use this size",
+                "N");
+    opts.optopt("u",
+                "unique",
+                "number of unique ngrams identifying a license (default 3)",
+                "N");
+    opts.optopt("m",
+                "min-confidence",
+                "minimal confidence of reported ngram matches between 0 and 1 (default 0.6), \
+                 1 requires all signature ngrams",
+                "X");
+    opts.optopt("f",
+                "fusion",
+                "how ngram and ssdeep results are reconciled: union, intersection or \
+                 prefer-ngram (default)",
+                "POLICY");
+    opts.optopt("s",
+                "ssdeep-threshold",
+                "minimal ssdeep similarity of reported licenses (default 75), thresholds \
+                 stored in the corpus for individual licenses take precedence",
+                "N");
+    opts.optflag("", "no-notices", "don't search for license notices");
+    opts.optflag("",
+                 "segment",
+                 "split files bundling several licenses into segments of single licenses");
+    opts.optflag("",
+                 "explain",
+                 "report differences from the texts of the licenses found, changes outside \
+                  of template variables mark the license as modified");
+    opts.optflag("",
+                 "validate-vars",
+                 "reject ngram matches whose SPDX template variables don't match their \
+                  pattern");
+    opts.optopt("",
+                "convert",
+                "with -c convert the data corpus to json or binary, the binary corpus is \
+                 memory-mapped and loaded in place of ngrams.json",
+                "FORMAT");
+    opts.optopt("C",
+                "config",
+                "read detector settings from a JSON config file, with -g per-license ssdeep \
+                 thresholds are stored in the corpus",
+                "FILE");
+    opts.optopt("o",
+                "output",
+                "output format of -c: json (default), spdx (tag-value), spdx-json, cyclonedx \
+                 (JSON), cyclonedx-xml or sarif",
+                "FORMAT");
+    opts.optopt("",
+                "allow",
+                "comma separated list of acceptable licenses, others are reported as policy \
+                 violations in SARIF output",
+                "LICENSES");
+    opts.optopt("",
+                "deny",
+                "comma separated list of licenses reported as policy violations in SARIF \
+                 output",
+                "LICENSES");
     opts.optflag("v", "verbose", "verbose mode");
     opts.optflag("", "version", "display version information");
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
-        Err(e) => panic!(e.to_string()),
+        Err(e) => {
+            writeln!(&mut std::io::stderr(), "[E] {}", e).ok();
+            print_usage(1, program, &opts);
+            return;
+        }
     };
 
-    // ssdeep::compute_directory("data/");
-    // ssdeep::compare("license_hashes", "../docker-hica/", 75);
-
     if matches.opt_present("h") {
         print_usage(0, program, &opts);
     }
-
-    let verbose = matches.opt_present("v");
-    let is_check = matches.opt_present("c");
-    let is_generate = matches.opt_present("g");
-    if is_generate && is_check {
-        panic!("Options -g and -c are mutually exclusive");
-    } else if !is_generate && !is_check {
-        panic!("Provide either -g or -c argument");
+    if matches.opt_present("version") {
+        println!("{} {}", program, env!("CARGO_PKG_VERSION"));
+        return;
     }
 
-    let check_data = match matches.opt_str("c") {
-        Some(x) => x,
-        None => String::new(),
-    };
-    let gen_data = match matches.opt_str("g") {
-        Some(x) => x,
-        None => String::new(),
-    };
-
-    if is_check {
-        if check_data == "" {
-            panic!("Empty check data");
-        }
-
-        if matches.free.is_empty() {
-            panic!("Nothing to check");
-        }
-
-        let output = search_path(&check_data, &matches.free[0]);
-        println!("{}", output);
-    } else {
-        if gen_data == "" {
-            panic!("No target directory from which to generate data");
+    if let Err(e) = run(&matches) {
+        writeln!(&mut std::io::stderr(), "[E] {}", e).ok();
+        if e.kind() == liceum::ErrorKind::Usage {
+            print_usage(1, program, &opts);
         }
-
-        fs::create_dir("cache/").ok();
-
-        let output = generate_corpuses(&gen_data, verbose);
-        let ngrams = Path::new("cache/").join(NGRAMS_FILE);
-        write_file(ngrams.to_str().unwrap(), &output).ok();
-
-        let ssdeep = ssdeep::compute_directory(&gen_data);
-        let hashes = Path::new("cache/").join(SSDEEP_HASHES);
-        write_file(hashes.to_str().unwrap(), &ssdeep).ok();
+        std::process::exit(1);
     }
 }
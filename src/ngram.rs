@@ -1,41 +1,71 @@
-#![allow(unknown_lints, derive_hash_xor_eq)]
-use std::iter::{FromIterator, IntoIterator};
 use std::hash::{Hash, Hasher};
 
-/// Struct representing a hashable vector where the final hash
-/// is the sum of its elements hashes.
-#[derive(Eq, PartialEq, Debug, RustcDecodable, RustcEncodable)]
-pub struct HashableVec<T: Clone + Eq + Hash> {
-    pub obj: Vec<T>,
-}
+/// FNV-1a offset basis and prime
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
 
-impl<T: Clone + Eq + Hash> HashableVec<T> {
-    pub fn new<I: IntoIterator<Item = T>>(iter: I) -> HashableVec<T> {
-        HashableVec { obj: Vec::from_iter(iter) }
-    }
+/// Base of the polynomial rolling hash over token hashes
+const BASE: u64 = 0x9e3779b97f4a7c15;
+
+/// Holds an ngram of consecutive tokens as the 64-bit hash of the tokens.
+///
+/// Hashes are stable across platforms and runs, corpora store them in
+/// place of the tokens.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct NGram {
+    pub hash: u64,
 }
 
-impl<T: Clone + Eq + Hash> Hash for HashableVec<T> {
+impl Hash for NGram {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        for item in &self.obj {
-            item.hash(state)
-        }
+        state.write_u64(self.hash)
     }
 }
 
-/// Holds `NGram` of type `T` and size `size`
-// TODO: hash^eq
-#[derive(Hash, Eq, PartialEq, Debug, RustcDecodable, RustcEncodable)]
-pub struct NGram<T: Clone + Eq + Hash> {
-    pub size: usize,
-    pub elements: HashableVec<T>,
+/// Stable hash of a single token.
+pub fn hash_token(token: &str) -> u64 {
+    token.bytes().fold(FNV_OFFSET, |h, b| (h ^ b as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// Spread the bits of a rolling hash, a bijection so no collisions are
+/// added.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
 }
 
-impl<T: Clone + Eq + Hash> NGram<T> {
-    pub fn new(items: &[T]) -> NGram<T> {
-        NGram {
-            size: items.len(),
-            elements: HashableVec::new(items.iter().cloned()),
+impl NGram {
+    /// Ngram of `items`.
+    pub fn new<S: AsRef<str>>(items: &[S]) -> NGram {
+        let hashes: Vec<u64> = items.iter().map(|x| hash_token(x.as_ref())).collect();
+        NGram::windows(&hashes, hashes.len()).pop().unwrap_or(NGram::from_hash(mix(0)))
+    }
+
+    /// Ngram stored as `hash`.
+    pub fn from_hash(hash: u64) -> NGram {
+        NGram { hash: hash }
+    }
+
+    /// Ngrams of every window of `n` consecutive tokens given by their
+    /// `hashes`, computed by a rolling hash in a single pass.
+    pub fn windows(hashes: &[u64], n: usize) -> Vec<NGram> {
+        if n == 0 || hashes.len() < n {
+            return Vec::new();
+        }
+
+        // BASE^(n - 1), weight of the token leaving the window
+        let top = (1..n).fold(1u64, |x, _| x.wrapping_mul(BASE));
+        let mut rolling = hashes[..n].iter()
+                                     .fold(0u64, |h, x| h.wrapping_mul(BASE).wrapping_add(*x));
+        let mut out: Vec<NGram> = Vec::with_capacity(hashes.len() - n + 1);
+        out.push(NGram::from_hash(mix(rolling)));
+        for i in n..hashes.len() {
+            rolling = rolling.wrapping_sub(hashes[i - n].wrapping_mul(top))
+                             .wrapping_mul(BASE)
+                             .wrapping_add(hashes[i]);
+            out.push(NGram::from_hash(mix(rolling)));
         }
+        out
     }
 }
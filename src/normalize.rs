@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use regex::Regex;
+
+/// Varietal spellings treated as the same word, as listed by the SPDX
+/// License Matching Guidelines. Variants are mapped onto the first form,
+/// multi-word variants are matched as consecutive tokens.
+static EQUIVALENT_WORDS: &'static [(&'static str, &'static str)] = &[
+    ("acknowledgment", "acknowledgement"),
+    ("analog", "analogue"),
+    ("analyze", "analyse"),
+    ("artifact", "artefact"),
+    ("authorization", "authorisation"),
+    ("authorized", "authorised"),
+    ("caliber", "calibre"),
+    ("canceled", "cancelled"),
+    ("capitalizations", "capitalisations"),
+    ("catalog", "catalogue"),
+    ("categorize", "categorise"),
+    ("center", "centre"),
+    ("copyright holder", "copyright owner"),
+    ("emphasized", "emphasised"),
+    ("favor", "favour"),
+    ("favorite", "favourite"),
+    ("fulfill", "fulfil"),
+    ("fulfillment", "fulfilment"),
+    ("http", "https"),
+    ("initialize", "initialise"),
+    ("judgment", "judgement"),
+    ("labeling", "labelling"),
+    ("labor", "labour"),
+    ("license", "licence"),
+    ("licenses", "licences"),
+    ("licensed", "licenced"),
+    ("licensing", "licencing"),
+    ("licensor", "licencor"),
+    ("maximize", "maximise"),
+    ("modeled", "modelled"),
+    ("modeling", "modelling"),
+    ("noncommercial", "non commercial"),
+    ("offense", "offence"),
+    ("optimize", "optimise"),
+    ("organization", "organisation"),
+    ("organize", "organise"),
+    ("percent", "per cent"),
+    ("practice", "practise"),
+    ("program", "programme"),
+    ("realize", "realise"),
+    ("recognize", "recognise"),
+    ("signaling", "signalling"),
+    ("sublicense", "sub license"),
+    ("utilization", "utilisation"),
+    ("while", "whilst"),
+    ("whitespace", "white space"),
+    ("willful", "wilful"),
+];
+
+/// Settings of the text normalization applied before ngram extraction.
+///
+/// Each step implements one of the SPDX License Matching Guidelines, the
+/// settings are stored in the generated corpus so that searching applies
+/// exactly the same pipeline as generation did.
+#[derive(RustcDecodable, RustcEncodable, Debug, Clone, PartialEq, Eq)]
+pub struct Normalization {
+    /// Fold all text to lower case
+    pub case: bool,
+    /// Treat quote and dash variants as equivalent and ignore punctuation
+    pub punctuation: bool,
+    /// Remove bullets and list markers at the start of lines
+    pub bullets: bool,
+    /// Map varietal spellings onto a single form
+    pub words: bool,
+    /// Remove lines with copyright statements
+    pub copyright: bool,
+}
+
+impl Default for Normalization {
+    fn default() -> Normalization {
+        Normalization::all()
+    }
+}
+
+impl fmt::Display for Normalization {
+    /// Steps in the form accepted by `Normalization::parse`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let steps: Vec<&str> = [(self.case, "case"),
+                                (self.punctuation, "punctuation"),
+                                (self.bullets, "bullets"),
+                                (self.words, "words"),
+                                (self.copyright, "copyright")]
+                                   .iter()
+                                   .filter(|x| x.0)
+                                   .map(|x| x.1)
+                                   .collect();
+        if steps.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", steps.join(","))
+        }
+    }
+}
+
+impl Normalization {
+    /// All normalization steps enabled.
+    pub fn all() -> Normalization {
+        Normalization {
+            case: true,
+            punctuation: true,
+            bullets: true,
+            words: true,
+            copyright: true,
+        }
+    }
+
+    /// Only whitespace and placeholder cleanup, as the ngram search
+    /// used to do.
+    pub fn none() -> Normalization {
+        Normalization {
+            case: false,
+            punctuation: false,
+            bullets: false,
+            words: false,
+            copyright: false,
+        }
+    }
+
+    /// Parse comma separated list of normalization steps, `all` and `none`
+    /// are accepted as shortcuts.
+    pub fn parse(spec: &str) -> Result<Normalization, String> {
+        let mut norm = Normalization::none();
+        for step in spec.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            match step {
+                "all" => norm = Normalization::all(),
+                "none" => norm = Normalization::none(),
+                "case" => norm.case = true,
+                "punctuation" => norm.punctuation = true,
+                "bullets" => norm.bullets = true,
+                "words" => norm.words = true,
+                "copyright" => norm.copyright = true,
+                _ => return Err(format!("Unknown normalization step: {}", step)),
+            }
+        }
+
+        Ok(norm)
+    }
+
+    /// Split `text` into normalized tokens.
+    pub fn tokens(&self, text: &str) -> Vec<String> {
+        self.spanned_tokens(text).into_iter().map(|x| x.text).collect()
+    }
+
+    /// Split `text` into normalized tokens keeping track of the part of
+    /// `text` every token comes from.
+    pub fn spanned_tokens(&self, text: &str) -> Vec<Token> {
+        PATTERNS.with(|p| self.normalize(text, p))
+    }
+
+    fn normalize(&self, text: &str, patterns: &Patterns) -> Vec<Token> {
+        let mut sanitized = Mapped::new(text);
+        sanitized.remove(&patterns.underscores);
+        sanitized.remove(&patterns.markup);
+
+        if self.copyright {
+            sanitized.remove(&patterns.copyright);
+        }
+
+        if self.bullets {
+            sanitized.remove(&patterns.bullets);
+        }
+
+        if self.punctuation {
+            sanitized.map_chars(|c| {
+                if is_quote(c) {
+                    // don't, "AS IS" and `quoted' are the same
+                    None
+                } else if c.is_alphanumeric() || c.is_whitespace() {
+                    Some(c)
+                } else {
+                    // dashes and remaining punctuation separate words
+                    Some(' ')
+                }
+            });
+        }
+
+        if self.case {
+            sanitized.map_chars(|c| c.to_lowercase());
+        }
+
+        let tokens = sanitized.tokens();
+        if self.words {
+            replace_equivalent_words(tokens, patterns)
+        } else {
+            tokens
+        }
+    }
+}
+
+/// Compiled patterns of the normalization steps.
+struct Patterns {
+    underscores: Regex,
+    markup: Regex,
+    copyright: Regex,
+    bullets: Regex,
+    // variant: canonical form, of single word variants
+    single: HashMap<&'static str, &'static str>,
+    multi: Vec<(Vec<&'static str>, &'static str)>,
+}
+
+impl Patterns {
+    fn new() -> Patterns {
+        let mut single: HashMap<&str, &str> = HashMap::new();
+        let mut multi: Vec<(Vec<&str>, &str)> = Vec::new();
+        for &(canonical, variant) in EQUIVALENT_WORDS {
+            let words: Vec<&str> = variant.split(' ').collect();
+            if words.len() == 1 {
+                single.insert(variant, canonical);
+            } else {
+                multi.push((words, canonical));
+            }
+        }
+
+        Patterns {
+            // ____...
+            underscores: Regex::new(r"_{2,}").unwrap(),
+            // <text_in_angle_brackets> and SPDX template markup such as
+            // <<var;name="x";original="<y>">> and <<beginOptional>>
+            markup: Regex::new(r#"<<(?:[^">]|"[^"]*")*>>|<[\w_]*>"#).unwrap(),
+            // Whole lines starting with a copyright statement, possibly
+            // inside of a comment. The word alone isn't enough, license
+            // texts wrap lines in front of it, it has to be followed by
+            // the sign, a year, a placeholder or "by".
+            copyright: Regex::new(concat!(r"(?im)^[ \t]*(?:[#*/;!-]+[ \t]*)?",
+                                          r"(?:copyright\b[ \t:]*(?:\(c\)|©|[0-9]{4}|\[|by\b)|",
+                                          r"\(c\)|©).*$"))
+                           .unwrap(),
+            // 1. 1.1 1) (1) a. a) (a) i. (iv) * - and friends at the start
+            // of lines
+            bullets: Regex::new(concat!(r"(?m)^[ \t]*(?:[0-9]+(?:\.[0-9]+)*[.)]|",
+                                        r"\([0-9]{1,3}\)|\(?[a-zA-Z]\)|[a-zA-Z]\.|",
+                                        r"\(?[ivxIVX]{1,5}[.)]|[*•·◦‣⁃+-])[ \t]+"))
+                         .unwrap(),
+            single: single,
+            multi: multi,
+        }
+    }
+}
+
+// Every thread compiles the patterns once, on first use
+thread_local!(static PATTERNS: Patterns = Patterns::new());
+
+/// Normalized token with the byte range of the original text it was
+/// produced from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    /// Byte offset of the start in the original text
+    pub start: usize,
+    /// Byte offset just past the end in the original text
+    pub end: usize,
+}
+
+/// Text in the middle of normalization together with the byte range of the
+/// original character every byte comes from.
+struct Mapped {
+    text: String,
+    spans: Vec<(usize, usize)>,
+}
+
+impl Mapped {
+    fn new(text: &str) -> Mapped {
+        let mut spans: Vec<(usize, usize)> = Vec::with_capacity(text.len());
+        for (i, c) in text.char_indices() {
+            for _ in 0..c.len_utf8() {
+                spans.push((i, i + c.len_utf8()));
+            }
+        }
+
+        Mapped {
+            text: String::from(text),
+            spans: spans,
+        }
+    }
+
+    /// Remove all matches of `re`.
+    fn remove(&mut self, re: &Regex) {
+        let mut text = String::with_capacity(self.text.len());
+        let mut spans: Vec<(usize, usize)> = Vec::with_capacity(self.spans.len());
+        let mut last = 0;
+        for (start, end) in re.find_iter(&self.text) {
+            text.push_str(&self.text[last..start]);
+            spans.extend_from_slice(&self.spans[last..start]);
+            last = end;
+        }
+        text.push_str(&self.text[last..]);
+        spans.extend_from_slice(&self.spans[last..]);
+
+        self.text = text;
+        self.spans = spans;
+    }
+
+    /// Replace every character by the characters `f` returns for it.
+    fn map_chars<F, I>(&mut self, f: F)
+        where F: Fn(char) -> I,
+              I: IntoIterator<Item = char>
+    {
+        let mut text = String::with_capacity(self.text.len());
+        let mut spans: Vec<(usize, usize)> = Vec::with_capacity(self.spans.len());
+        for (i, c) in self.text.char_indices() {
+            for m in f(c) {
+                text.push(m);
+                for _ in 0..m.len_utf8() {
+                    spans.push(self.spans[i]);
+                }
+            }
+        }
+
+        self.text = text;
+        self.spans = spans;
+    }
+
+    /// Split the text at whitespace.
+    fn tokens(&self) -> Vec<Token> {
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut start: Option<usize> = None;
+        for (i, c) in self.text.char_indices().chain(Some((self.text.len(), ' '))) {
+            match (start, c.is_whitespace()) {
+                (Some(s), true) => {
+                    tokens.push(Token {
+                        text: String::from(&self.text[s..i]),
+                        start: self.spans[s].0,
+                        end: self.spans[i - 1].1,
+                    });
+                    start = None;
+                }
+                (None, false) => start = Some(i),
+                _ => {}
+            }
+        }
+        tokens
+    }
+}
+
+/// Predicate determining whether `c` is any of the quote characters.
+fn is_quote(c: char) -> bool {
+    match c {
+        '\'' | '"' | '`' | '´' | '‘' | '’' | '‚' | '‛' | '“' | '”' | '„' | '‟' | '«' | '»' |
+        '‹' | '›' => true,
+        _ => false,
+    }
+}
+
+/// Replace varietal spellings in `tokens` by their canonical form.
+fn replace_equivalent_words(tokens: Vec<Token>, patterns: &Patterns) -> Vec<Token> {
+    // Canonical form spanning the original text of `tokens`
+    let canonical_tokens = |canonical: &str, tokens: &[Token]| -> Vec<Token> {
+        let (start, end) = (tokens[0].start, tokens[tokens.len() - 1].end);
+        canonical.split(' ')
+                 .map(|x| {
+                     Token {
+                         text: String::from(x),
+                         start: start,
+                         end: end,
+                     }
+                 })
+                 .collect()
+    };
+
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    'tokens: while i < tokens.len() {
+        for &(ref words, canonical) in &patterns.multi {
+            let end = i + words.len();
+            if end <= tokens.len() && tokens[i..end].iter().zip(words).all(|(a, b)| a.text == *b) {
+                out.extend(canonical_tokens(canonical, &tokens[i..end]));
+                i = end;
+                continue 'tokens;
+            }
+        }
+
+        match patterns.single.get(tokens[i].text.as_str()) {
+            Some(canonical) => out.extend(canonical_tokens(canonical, &tokens[i..i + 1])),
+            None => out.push(tokens[i].clone()),
+        }
+        i += 1;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_steps() {
+        assert_eq!(Normalization::parse("all").unwrap(), Normalization::all());
+        assert_eq!(Normalization::parse("").unwrap(), Normalization::none());
+        let norm = Normalization::parse("case, words").unwrap();
+        assert!(norm.case && norm.words && !norm.punctuation);
+        assert!(Normalization::parse("case,spelling").is_err());
+    }
+
+    #[test]
+    fn format_steps() {
+        assert_eq!(Normalization::none().to_string(), "none");
+        assert_eq!(Normalization::parse("words,case").unwrap().to_string(), "case,words");
+        for norm in &[Normalization::all(), Normalization::none()] {
+            assert_eq!(&Normalization::parse(&norm.to_string()).unwrap(), norm);
+        }
+    }
+
+    #[test]
+    fn remove_copyright_statements() {
+        let norm = Normalization::all();
+        for line in &["Copyright (c) 2016 Jane Doe",
+                      "  * Copyright 2009-2016 Foo Inc.",
+                      "# (C) 2016 Jane Doe",
+                      "Copyright [yyyy] [name of copyright owner]",
+                      "Copyright by the authors",
+                      "© Jane Doe"] {
+            assert_eq!(norm.tokens(&format!("{}\npermission", line)), vec!["permission"]);
+        }
+    }
+
+    #[test]
+    fn keep_wrapped_copyright_word() {
+        let norm = Normalization::all();
+        let wrapped = "with the\ncopyright notice that is included in or attached to the work";
+        assert_eq!(norm.tokens(wrapped), norm.tokens(&wrapped.replace("\n", " ")));
+    }
+
+    #[test]
+    fn spans_of_normalized_tokens() {
+        let norm = Normalization::all();
+        let text = "1. The Licence, \"AS IS\"";
+        let tokens = norm.spanned_tokens(text);
+        let words: Vec<&str> = tokens.iter().map(|x| x.text.as_str()).collect();
+        assert_eq!(words, vec!["the", "license", "as", "is"]);
+        assert_eq!(&text[tokens[1].start..tokens[1].end], "Licence");
+    }
+
+    #[test]
+    fn equivalent_multi_word_variants() {
+        let norm = Normalization::all();
+        assert_eq!(norm.tokens("any Sub-License"), vec!["any", "sublicense"]);
+        assert_eq!(norm.tokens("Copyright owner"), vec!["copyright", "holder"]);
+    }
+}
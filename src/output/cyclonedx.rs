@@ -0,0 +1,453 @@
+#![allow(non_snake_case)]
+use std::collections::BTreeMap;
+use std::iter;
+
+use rustc_serialize::{Encodable, Encoder};
+use rustc_serialize::json;
+
+use detector::{DetectionKind, ScanResult};
+use output::{self, TOOL_NAME, TOOL_VERSION};
+
+/// Version of the CycloneDX specification the documents follow.
+pub static SPEC_VERSION: &'static str = "1.5";
+
+/// XML namespace of the CycloneDX schema version.
+static XML_NAMESPACE: &'static str = "http://cyclonedx.org/schema/bom/1.5";
+
+#[derive(RustcEncodable)]
+struct Property {
+    name: String,
+    value: String,
+}
+
+impl Property {
+    fn new(name: &str, value: String) -> Property {
+        Property {
+            name: format!("{}:{}", TOOL_NAME, name),
+            value: value,
+        }
+    }
+}
+
+/// License identified either by SPDX `id` or a free form `name`
+struct License {
+    id: Option<String>,
+    name: Option<String>,
+    properties: Vec<Property>,
+}
+
+impl Encodable for License {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("License", 2, |s| {
+            match (&self.id, &self.name) {
+                (&Some(ref id), _) => try!(s.emit_struct_field("id", 0, |s| id.encode(s))),
+                (_, name) => try!(s.emit_struct_field("name", 0, |s| name.encode(s))),
+            }
+            s.emit_struct_field("properties", 1, |s| self.properties.encode(s))
+        })
+    }
+}
+
+#[derive(RustcEncodable)]
+struct LicenseChoice {
+    license: License,
+}
+
+#[derive(RustcEncodable)]
+struct Occurrence {
+    location: String,
+}
+
+#[derive(RustcEncodable)]
+struct Evidence {
+    occurrences: Vec<Occurrence>,
+    licenses: Vec<LicenseChoice>,
+}
+
+/// Component, `kind` is serialized as `type`
+struct Component {
+    kind: String,
+    name: String,
+    version: Option<String>,
+    evidence: Option<Evidence>,
+}
+
+impl Encodable for Component {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("Component", 4, |s| {
+            try!(s.emit_struct_field("type", 0, |s| self.kind.encode(s)));
+            try!(s.emit_struct_field("name", 1, |s| self.name.encode(s)));
+            if let Some(ref version) = self.version {
+                try!(s.emit_struct_field("version", 2, |s| version.encode(s)));
+            }
+            if let Some(ref evidence) = self.evidence {
+                try!(s.emit_struct_field("evidence", 3, |s| evidence.encode(s)));
+            }
+            Ok(())
+        })
+    }
+}
+
+#[derive(RustcEncodable)]
+struct Tools {
+    components: Vec<Component>,
+}
+
+#[derive(RustcEncodable)]
+struct Metadata {
+    timestamp: String,
+    tools: Tools,
+    component: Component,
+    /// Settings of the scan
+    properties: Vec<Property>,
+}
+
+/// Evidence of a single license collected over all scanned files
+#[derive(Default)]
+struct Found {
+    /// Identifier of the license on the SPDX license list, if known
+    spdx_id: Option<String>,
+    /// Highest confidence of the text detections, if any
+    confidence: Option<f64>,
+    ngram: bool,
+    ssdeep: bool,
+    notice: bool,
+    /// Expressions of `SPDX-License-Identifier` tags naming the license
+    expressions: Vec<String>,
+    locations: Vec<String>,
+    /// Corpora the detections come from
+    corpora: Vec<String>,
+}
+
+/// CycloneDX 1.5 BOM with the scanned directory as the described component,
+/// serializable both as JSON and XML.
+#[derive(RustcEncodable)]
+pub struct Bom {
+    bomFormat: String,
+    specVersion: String,
+    serialNumber: String,
+    version: u32,
+    metadata: Metadata,
+}
+
+impl Bom {
+    /// Create BOM from `result` of scanning `root`, every detected or
+    /// declared license is recorded once with the files it was found in and
+    /// the highest confidence of all of them.
+    pub fn new(result: &ScanResult, root: &str) -> Bom {
+        let created = output::timestamp();
+        let mut found: BTreeMap<String, Found> = BTreeMap::new();
+        let mut occurrences: Vec<Occurrence> = Vec::new();
+
+        for r in &result.files {
+            let tags: Vec<_> = r.tags
+                                .iter()
+                                .filter(|x| x.is_valid())
+                                .filter_map(|x| x.expression.as_ref().map(|e| (e, &x.listed)))
+                                .collect();
+            if r.detections.is_empty() && tags.is_empty() {
+                continue;
+            }
+
+            let location = output::relative_path(root, &r.file);
+            for d in &r.detections {
+                let f = found.entry(d.license.clone()).or_insert_with(Found::default);
+                f.confidence = Some(f.confidence.map_or(d.confidence, |x| x.max(d.confidence)));
+                f.ngram |= d.kind == DetectionKind::Text && d.ngram.is_some();
+                f.ssdeep |= d.ssdeep.is_some();
+                f.notice |= d.kind == DetectionKind::Notice;
+                f.spdx_id = f.spdx_id.take().or_else(|| d.spdx_id.clone());
+                if let Some(ref corpus) = d.corpus {
+                    if !f.corpora.contains(corpus) {
+                        f.corpora.push(corpus.clone());
+                    }
+                }
+                f.locations.push(location.clone());
+            }
+            for (e, listed) in tags {
+                for l in e.licenses() {
+                    let f = found.entry(l.id.clone()).or_insert_with(Found::default);
+                    if listed.contains(&l.id) {
+                        f.spdx_id = Some(l.id.clone());
+                    }
+                    if !f.expressions.contains(&e.to_string()) {
+                        f.expressions.push(e.to_string());
+                    }
+                    if !f.locations.contains(&location) {
+                        f.locations.push(location.clone());
+                    }
+                }
+            }
+            occurrences.push(Occurrence { location: location });
+        }
+
+        let mut licenses: Vec<LicenseChoice> = Vec::new();
+        for (name, f) in found {
+            let mut properties: Vec<Property> = Vec::new();
+            if let Some(confidence) = f.confidence {
+                properties.push(Property::new("confidence", confidence.to_string()));
+            }
+            if f.ngram {
+                properties.push(Property::new("method", String::from("ngram")));
+            }
+            if f.ssdeep {
+                properties.push(Property::new("method", String::from("ssdeep")));
+            }
+            if f.notice {
+                properties.push(Property::new("method", String::from("notice")));
+            }
+            if !f.expressions.is_empty() {
+                properties.push(Property::new("method", String::from("spdx-identifier")));
+            }
+            for e in f.expressions {
+                properties.push(Property::new("expression", e));
+            }
+            for c in f.corpora {
+                properties.push(Property::new("corpus", c));
+            }
+            for l in f.locations {
+                properties.push(Property::new("location", l));
+            }
+
+            // `id` is restricted to the SPDX license list
+            let name = if f.spdx_id.is_some() { None } else { Some(name) };
+            licenses.push(LicenseChoice {
+                license: License {
+                    id: f.spdx_id,
+                    name: name,
+                    properties: properties,
+                },
+            });
+        }
+
+        let evidence = if licenses.is_empty() {
+            None
+        } else {
+            Some(Evidence {
+                occurrences: occurrences,
+                licenses: licenses,
+            })
+        };
+
+        Bom {
+            bomFormat: String::from("CycloneDX"),
+            specVersion: String::from(SPEC_VERSION),
+            serialNumber: format!("urn:uuid:{}", output::scan_uuid(result, &created)),
+            version: 1,
+            metadata: Metadata {
+                timestamp: created,
+                tools: Tools {
+                    components: vec![Component {
+                                         kind: String::from("application"),
+                                         name: String::from(TOOL_NAME),
+                                         version: Some(String::from(TOOL_VERSION)),
+                                         evidence: None,
+                                     }],
+                },
+                component: Component {
+                    kind: String::from("application"),
+                    name: output::root_name(root),
+                    version: None,
+                    evidence: evidence,
+                },
+                properties: vec![Property::new("ssdeep_threshold",
+                                               result.ssdeep_threshold.to_string())],
+            },
+        }
+    }
+
+    /// Format the BOM in the CycloneDX JSON serialization.
+    pub fn to_json(&self) -> String {
+        let pj = json::as_pretty_json(self).indent(3);
+        format!("{}", pj)
+    }
+
+    /// Format the BOM in the CycloneDX XML serialization.
+    pub fn to_xml(&self) -> String {
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str(&format!("<bom xmlns=\"{}\" serialNumber=\"{}\" version=\"{}\">\n",
+                              XML_NAMESPACE,
+                              escape(&self.serialNumber),
+                              self.version));
+        out.push_str("  <metadata>\n");
+        out.push_str(&format!("    <timestamp>{}</timestamp>\n",
+                              escape(&self.metadata.timestamp)));
+        out.push_str("    <tools>\n      <components>\n");
+        for c in &self.metadata.tools.components {
+            component_xml(&mut out, c, 8);
+        }
+        out.push_str("      </components>\n    </tools>\n");
+        component_xml(&mut out, &self.metadata.component, 4);
+        properties_xml(&mut out, &self.metadata.properties, 4);
+        out.push_str("  </metadata>\n</bom>\n");
+        out
+    }
+}
+
+/// Escape characters with special meaning in XML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Append XML of `properties` indented by `indent` spaces to `out`.
+fn properties_xml(out: &mut String, properties: &[Property], indent: usize) {
+    let pad: String = iter::repeat(' ').take(indent).collect();
+    out.push_str(&format!("{}<properties>\n", pad));
+    for p in properties {
+        out.push_str(&format!("{}  <property name=\"{}\">{}</property>\n",
+                              pad,
+                              escape(&p.name),
+                              escape(&p.value)));
+    }
+    out.push_str(&format!("{}</properties>\n", pad));
+}
+
+/// Append XML of `component` indented by `indent` spaces to `out`.
+fn component_xml(out: &mut String, component: &Component, indent: usize) {
+    let pad: String = iter::repeat(' ').take(indent).collect();
+    out.push_str(&format!("{}<component type=\"{}\">\n", pad, escape(&component.kind)));
+    out.push_str(&format!("{}  <name>{}</name>\n", pad, escape(&component.name)));
+    if let Some(ref version) = component.version {
+        out.push_str(&format!("{}  <version>{}</version>\n", pad, escape(version)));
+    }
+
+    if let Some(ref evidence) = component.evidence {
+        out.push_str(&format!("{}  <evidence>\n", pad));
+        out.push_str(&format!("{}    <occurrences>\n", pad));
+        for o in &evidence.occurrences {
+            out.push_str(&format!("{}      <occurrence>\n{}        <location>{}</location>\n\
+                                   {}      </occurrence>\n",
+                                  pad,
+                                  pad,
+                                  escape(&o.location),
+                                  pad));
+        }
+        out.push_str(&format!("{}    </occurrences>\n", pad));
+        out.push_str(&format!("{}    <licenses>\n", pad));
+        for choice in &evidence.licenses {
+            let l = &choice.license;
+            out.push_str(&format!("{}      <license>\n", pad));
+            match (&l.id, &l.name) {
+                (&Some(ref id), _) => {
+                    out.push_str(&format!("{}        <id>{}</id>\n", pad, escape(id)))
+                }
+                (_, &Some(ref name)) => {
+                    out.push_str(&format!("{}        <name>{}</name>\n", pad, escape(name)))
+                }
+                _ => {}
+            }
+            properties_xml(out, &l.properties, indent + 8);
+            out.push_str(&format!("{}      </license>\n", pad));
+        }
+        out.push_str(&format!("{}    </licenses>\n", pad));
+        out.push_str(&format!("{}  </evidence>\n", pad));
+    }
+
+    out.push_str(&format!("{}</component>\n", pad));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use output::tests::{ROOT, detection, file, result, tag};
+
+    // id, name and properties of a license
+    type Entry = (Option<String>, Option<String>, Vec<(String, String)>);
+
+    /// Licenses of the evidence of `bom`.
+    fn licenses(bom: &Bom) -> Vec<Entry> {
+        bom.metadata
+           .component
+           .evidence
+           .as_ref()
+           .map_or(Vec::new(), |e| {
+               e.licenses
+                .iter()
+                .map(|x| {
+                    (x.license.id.clone(),
+                     x.license.name.clone(),
+                     x.license
+                      .properties
+                      .iter()
+                      .map(|p| (p.name.clone(), p.value.clone()))
+                      .collect())
+                })
+                .collect()
+           })
+    }
+
+    #[test]
+    fn listed_license_id() {
+        let scan = result(vec![file("LICENSE",
+                                    vec![detection("MIT", Some("MIT"), 1.0),
+                                         detection("Custom", None, 1.0)],
+                                    vec![tag("Apache-2.0 OR LicenseRef-Own", &["Apache-2.0"])])]);
+        let bom = Bom::new(&scan, ROOT);
+        let ids: Vec<(Option<String>, Option<String>)> = licenses(&bom)
+                                                             .into_iter()
+                                                             .map(|x| (x.0, x.1))
+                                                             .collect();
+        assert_eq!(ids,
+                   vec![(Some(String::from("Apache-2.0")), None),
+                        (None, Some(String::from("Custom"))),
+                        (None, Some(String::from("LicenseRef-Own"))),
+                        (Some(String::from("MIT")), None)]);
+
+        let json = bom.to_json();
+        assert!(json.contains("\"id\": \"MIT\"") && json.contains("\"name\": \"Custom\""));
+        let xml = bom.to_xml();
+        assert!(xml.contains("<id>MIT</id>") && xml.contains("<name>Custom</name>"));
+    }
+
+    #[test]
+    fn escape_xml() {
+        let scan = result(vec![file("a&b.txt", vec![detection("<\"Own's\">", None, 1.0)], vec![])]);
+        let xml = Bom::new(&scan, ROOT).to_xml();
+        assert!(xml.contains("<name>&lt;&quot;Own&apos;s&quot;&gt;</name>"));
+        assert!(xml.contains("<location>a&amp;b.txt</location>"));
+        assert!(xml.contains("<property name=\"liceum:location\">a&amp;b.txt</property>"));
+    }
+
+    #[test]
+    fn evidence_properties() {
+        let mut fused = detection("MIT", Some("MIT"), 0.9);
+        fused.ssdeep = Some(80);
+        let scan = result(vec![file("LICENSE", vec![detection("MIT", Some("MIT"), 1.0)], vec![]),
+                               file("README", vec![], vec![]),
+                               file("src/a.c", vec![fused], vec![]),
+                               file("src/b.c", vec![], vec![tag("MIT", &["MIT"])])]);
+        let bom = Bom::new(&scan, ROOT);
+
+        let occurrences: Vec<&str> = bom.metadata
+                                        .component
+                                        .evidence
+                                        .as_ref()
+                                        .unwrap()
+                                        .occurrences
+                                        .iter()
+                                        .map(|x| x.location.as_str())
+                                        .collect();
+        assert_eq!(occurrences, vec!["LICENSE", "src/a.c", "src/b.c"]);
+
+        let property = |name: &str, value: &str| {
+            (format!("liceum:{}", name), String::from(value))
+        };
+        assert_eq!(licenses(&bom),
+                   vec![(Some(String::from("MIT")),
+                         None,
+                         vec![property("confidence", "1"),
+                              property("method", "ngram"),
+                              property("method", "ssdeep"),
+                              property("method", "spdx-identifier"),
+                              property("expression", "MIT"),
+                              property("corpus", "base"),
+                              property("location", "LICENSE"),
+                              property("location", "src/a.c"),
+                              property("location", "src/b.c")])]);
+        assert_eq!(bom.metadata.properties[0].value, "75");
+    }
+}
@@ -0,0 +1,146 @@
+use std::path::Path;
+
+use time;
+
+use detector::ScanResult;
+use pathex::AbsolutePath;
+use util::sha1_hex;
+
+pub mod spdx;
+pub mod cyclonedx;
+pub mod sarif;
+
+/// Name under which liceum identifies itself in generated documents.
+pub static TOOL_NAME: &'static str = "liceum";
+
+/// Version of liceum recorded in generated documents.
+pub static TOOL_VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+/// Path of `file` relative to the scanned `root`, both are canonicalized
+/// the same way `Detector::scan_file` does.
+pub fn relative_path(root: &str, file: &str) -> String {
+    let root = Path::new(root).canonical_path();
+    let file = Path::new(file).canonical_path();
+    match file.strip_prefix(&root) {
+        Ok(rel) if !rel.as_os_str().is_empty() => rel.to_string_lossy().into_owned(),
+        // `root` is the scanned file itself
+        _ => {
+            file.file_name()
+                .map_or(file.to_string_lossy(), |x| x.to_string_lossy())
+                .into_owned()
+        }
+    }
+}
+
+/// Name of the scanned `root` used as a document or component name.
+pub fn root_name(root: &str) -> String {
+    Path::new(root)
+        .canonical_path()
+        .file_name()
+        .map_or(String::from(TOOL_NAME), |x| x.to_string_lossy().into_owned())
+}
+
+/// Digest identifying a scan of `result` at time `created`, unique for
+/// every scan of different contents or at different time.
+pub fn scan_digest(result: &ScanResult, created: &str) -> String {
+    let mut seed = String::from(created);
+    for r in &result.files {
+        seed.push_str(&r.file);
+        seed.push_str(&r.sha1);
+    }
+    sha1_hex(seed.as_bytes())
+}
+
+/// Name based (version 5 layout) UUID derived from `scan_digest`.
+pub fn scan_uuid(result: &ScanResult, created: &str) -> String {
+    let h = scan_digest(result, created);
+    let variant = ['8', '9', 'a', 'b'][usize::from_str_radix(&h[16..17], 16).unwrap_or(0) & 3];
+    format!("{}-{}-5{}-{}{}-{}",
+            &h[0..8],
+            &h[8..12],
+            &h[13..16],
+            variant,
+            &h[17..20],
+            &h[20..32])
+}
+
+/// Current UTC time formatted according to RFC 3339.
+pub fn timestamp() -> String {
+    format!("{}", time::now_utc().rfc3339())
+}
+
+/// Scan results the output formats are tested with.
+#[cfg(test)]
+pub mod tests {
+    use detector::{Detection, DetectionKind, FileResult, LicenseTag, NgramMatch, ScanResult,
+                   Span};
+    use spdx::expression::Expression;
+
+    /// Scanned directory the files are in.
+    pub static ROOT: &'static str = "/project";
+
+    /// First line of a file.
+    pub fn span() -> Span {
+        Span {
+            start_line: 1,
+            end_line: 1,
+            start_byte: 0,
+            end_byte: 10,
+        }
+    }
+
+    /// Text of `license` found by ngrams with `confidence` in corpus `base`.
+    pub fn detection(license: &str, spdx_id: Option<&str>, confidence: f64) -> Detection {
+        Detection {
+            license: String::from(license),
+            kind: DetectionKind::Text,
+            ngram: Some(NgramMatch {
+                found: 3,
+                total: 3,
+                level: 1,
+            }),
+            ssdeep: None,
+            confidence: confidence,
+            matches: vec![span()],
+            region: span(),
+            file_coverage: 1.0,
+            corpus: Some(String::from("base")),
+            spdx_id: spdx_id.map(String::from),
+        }
+    }
+
+    /// Tag declaring `value`, whose licenses in `listed` are on the SPDX
+    /// license list.
+    pub fn tag(value: &str, listed: &[&str]) -> LicenseTag {
+        let expression = Expression::parse(value);
+        LicenseTag {
+            line: 1,
+            value: String::from(value),
+            error: expression.as_ref().err().cloned(),
+            expression: expression.ok(),
+            unknown: Vec::new(),
+            deprecated: Vec::new(),
+            listed: listed.iter().map(|x| String::from(*x)).collect(),
+        }
+    }
+
+    /// Result of file `name` of `ROOT`.
+    pub fn file(name: &str, detections: Vec<Detection>, tags: Vec<LicenseTag>) -> FileResult {
+        FileResult {
+            file: format!("{}/{}", ROOT, name),
+            sha1: format!("{:040}", 0),
+            detections: detections,
+            tags: tags,
+            segments: Vec::new(),
+            explanations: Vec::new(),
+        }
+    }
+
+    pub fn result(files: Vec<FileResult>) -> ScanResult {
+        ScanResult {
+            files: files,
+            errors: Vec::new(),
+            ssdeep_threshold: 75,
+        }
+    }
+}
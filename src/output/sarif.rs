@@ -0,0 +1,543 @@
+#![allow(non_snake_case)]
+use std::collections::HashMap;
+use std::path::Path;
+
+use rustc_serialize::{Encodable, Encoder};
+use rustc_serialize::json;
+
+use detector::{Detection, DetectionKind, ScanResult, Span};
+use output::{self, TOOL_NAME, TOOL_VERSION};
+use pathex::AbsolutePath;
+use policy::{Policy, Verdict};
+
+/// Version of the SARIF specification the logs follow.
+pub static SARIF_VERSION: &'static str = "2.1.0";
+
+static SARIF_SCHEMA: &'static str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+static INFORMATION_URI: &'static str = "https://github.com/shaded-enmity/liceum";
+
+/// Base of all artifact locations, the scanned directory.
+static SRCROOT: &'static str = "%SRCROOT%";
+
+/// Names of files expected to contain a license text, compared as
+/// lowercase prefixes.
+static LICENSE_FILES: &'static [&'static str] = &["license", "licence", "copying", "copyright",
+                                                  "unlicense"];
+
+/// Rules reported by liceum: (id, name, description, level)
+static RULES: &'static [(&'static str, &'static str, &'static str, &'static str)] = &[
+    ("LIC001", "LicenseDetected", "License detected", "note"),
+    ("LIC002", "LicenseDenied", "License denied by policy", "error"),
+    ("LIC003", "LicenseNotAllowed", "License not on the allow list", "error"),
+    ("LIC004", "UnknownLicense", "License file with no recognized license", "warning"),
+    ("LIC005", "UnreadableFile", "File could not be scanned", "warning"),
+    ("LIC006", "InvalidLicenseIdentifier", "Invalid SPDX-License-Identifier tag", "warning"),
+    ("LIC007", "ModifiedLicense", "License text modified outside of its variable parts", "warning"),
+];
+
+const DETECTED: usize = 0;
+const DENIED: usize = 1;
+const NOT_ALLOWED: usize = 2;
+const UNKNOWN: usize = 3;
+const UNREADABLE: usize = 4;
+const INVALID_TAG: usize = 5;
+const MODIFIED: usize = 6;
+
+#[derive(RustcEncodable)]
+struct Message {
+    text: String,
+}
+
+#[derive(RustcEncodable)]
+struct Configuration {
+    level: String,
+}
+
+#[derive(RustcEncodable)]
+struct Rule {
+    id: String,
+    name: String,
+    shortDescription: Message,
+    defaultConfiguration: Configuration,
+}
+
+#[derive(RustcEncodable)]
+struct Driver {
+    name: String,
+    version: String,
+    informationUri: String,
+    rules: Vec<Rule>,
+}
+
+#[derive(RustcEncodable)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(RustcEncodable)]
+struct ArtifactLocation {
+    uri: String,
+    uriBaseId: String,
+}
+
+/// Part of an artifact, the end and byte range are omitted when unknown
+struct Region {
+    startLine: usize,
+    endLine: Option<usize>,
+    byteOffset: Option<usize>,
+    byteLength: Option<usize>,
+}
+
+impl Region {
+    fn line(line: usize) -> Region {
+        Region {
+            startLine: line,
+            endLine: None,
+            byteOffset: None,
+            byteLength: None,
+        }
+    }
+
+    fn span(span: &Span) -> Region {
+        Region {
+            startLine: span.start_line,
+            endLine: Some(span.end_line),
+            byteOffset: Some(span.start_byte),
+            byteLength: Some(span.end_byte - span.start_byte),
+        }
+    }
+}
+
+impl Encodable for Region {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("Region", 4, |s| {
+            try!(s.emit_struct_field("startLine", 0, |s| self.startLine.encode(s)));
+            let optional = [("endLine", self.endLine),
+                            ("byteOffset", self.byteOffset),
+                            ("byteLength", self.byteLength)];
+            for (i, &(name, value)) in optional.iter().enumerate() {
+                if let Some(value) = value {
+                    try!(s.emit_struct_field(name, i + 1, |s| value.encode(s)));
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[derive(RustcEncodable)]
+struct PhysicalLocation {
+    artifactLocation: ArtifactLocation,
+    region: Region,
+}
+
+#[derive(RustcEncodable)]
+struct Location {
+    physicalLocation: PhysicalLocation,
+}
+
+/// Details of a detected license, absent ones are omitted
+#[derive(Default)]
+struct FindingProperties {
+    confidence: Option<f64>,
+    /// Corpus the license comes from
+    corpus: Option<String>,
+}
+
+impl FindingProperties {
+    fn is_empty(&self) -> bool {
+        self.confidence.is_none() && self.corpus.is_none()
+    }
+}
+
+impl Encodable for FindingProperties {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("FindingProperties", 2, |s| {
+            if let Some(confidence) = self.confidence {
+                try!(s.emit_struct_field("confidence", 0, |s| confidence.encode(s)));
+            }
+            if let Some(ref corpus) = self.corpus {
+                try!(s.emit_struct_field("corpus", 1, |s| corpus.encode(s)));
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Single finding, `properties` are omitted when empty
+struct Finding {
+    ruleId: String,
+    ruleIndex: usize,
+    level: String,
+    message: Message,
+    locations: Vec<Location>,
+    properties: FindingProperties,
+}
+
+impl Encodable for Finding {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("Finding", 6, |s| {
+            try!(s.emit_struct_field("ruleId", 0, |s| self.ruleId.encode(s)));
+            try!(s.emit_struct_field("ruleIndex", 1, |s| self.ruleIndex.encode(s)));
+            try!(s.emit_struct_field("level", 2, |s| self.level.encode(s)));
+            try!(s.emit_struct_field("message", 3, |s| self.message.encode(s)));
+            try!(s.emit_struct_field("locations", 4, |s| self.locations.encode(s)));
+            if !self.properties.is_empty() {
+                try!(s.emit_struct_field("properties", 5, |s| self.properties.encode(s)));
+            }
+            Ok(())
+        })
+    }
+}
+
+#[derive(RustcEncodable)]
+struct BaseUri {
+    uri: String,
+}
+
+/// Settings of the scan
+#[derive(RustcEncodable)]
+struct RunProperties {
+    ssdeep_threshold: u32,
+}
+
+#[derive(RustcEncodable)]
+struct Run {
+    tool: Tool,
+    originalUriBaseIds: HashMap<String, BaseUri>,
+    results: Vec<Finding>,
+    properties: RunProperties,
+}
+
+/// SARIF 2.1.0 log with a single run reporting detected and declared
+/// licenses, policy violations, license files without a recognized license,
+/// invalid `SPDX-License-Identifier` tags, modified license texts and files
+/// which couldn't be scanned.
+pub struct Log {
+    runs: Vec<Run>,
+}
+
+impl Encodable for Log {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("Log", 3, |s| {
+            try!(s.emit_struct_field("$schema", 0, |s| SARIF_SCHEMA.encode(s)));
+            try!(s.emit_struct_field("version", 1, |s| SARIF_VERSION.encode(s)));
+            s.emit_struct_field("runs", 2, |s| self.runs.encode(s))
+        })
+    }
+}
+
+/// Predicate determining whether `file` is named like a license file.
+fn is_license_file(file: &str) -> bool {
+    Path::new(file).file_name().map_or(false, |x| {
+        let name = x.to_string_lossy().to_lowercase();
+        LICENSE_FILES.iter().any(|p| name.starts_with(p))
+    })
+}
+
+/// Percent-encode every segment of `path`, all characters except the
+/// unreserved ones of RFC 3986 are encoded.
+fn encode_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for b in path.bytes() {
+        match b {
+            b'a'...b'z' | b'A'...b'Z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// Create finding of rule `RULES[rule]` at `region` of `file` relative to
+/// `root`.
+fn finding(rule: usize, root: &str, file: &str, region: Region, text: String) -> Finding {
+    Finding {
+        ruleId: String::from(RULES[rule].0),
+        ruleIndex: rule,
+        level: String::from(RULES[rule].3),
+        message: Message { text: text },
+        locations: vec![Location {
+                            physicalLocation: PhysicalLocation {
+                                artifactLocation: ArtifactLocation {
+                                    uri: encode_path(&output::relative_path(root, file)),
+                                    uriBaseId: String::from(SRCROOT),
+                                },
+                                region: region,
+                            },
+                        }],
+        properties: FindingProperties::default(),
+    }
+}
+
+/// Describe how `d` was found.
+fn describe(d: &Detection) -> String {
+    let mut evidence: Vec<String> = Vec::new();
+    if let Some(ref m) = d.ngram {
+        evidence.push(format!("{} of {} signature ngrams", m.found, m.total));
+    }
+    if let Some(score) = d.ssdeep {
+        evidence.push(format!("ssdeep similarity {}", score));
+    }
+    let license = match d.kind {
+        DetectionKind::Text => d.license.clone(),
+        DetectionKind::Notice => format!("notice for {}", d.license),
+    };
+    format!("{} (confidence {:.2}, {})",
+            license,
+            d.confidence,
+            evidence.join(", "))
+}
+
+impl Log {
+    /// Create log from `result` of scanning `root`, licenses are checked
+    /// against `policy`.
+    pub fn new(result: &ScanResult, root: &str, policy: &Policy) -> Log {
+        let mut results: Vec<Finding> = Vec::new();
+
+        for r in &result.files {
+            if r.detections.is_empty() && is_license_file(&r.file) {
+                results.push(finding(UNKNOWN,
+                                     root,
+                                     &r.file,
+                                     Region::line(1),
+                                     String::from("No known license recognized in license \
+                                                   file")));
+            }
+
+            for d in &r.detections {
+                // The policy names licenses by their SPDX identifiers
+                let license = d.spdx_id.as_ref().unwrap_or(&d.license);
+                let (rule, text) = match policy.check(license) {
+                    Verdict::Allowed => (DETECTED, format!("License {}", describe(d))),
+                    Verdict::Denied => (DENIED, format!("Denied license {}", describe(d))),
+                    Verdict::NotAllowed => {
+                        (NOT_ALLOWED, format!("Not allowed license {}", describe(d)))
+                    }
+                };
+
+                let mut f = finding(rule, root, &r.file, Region::span(&d.region), text);
+                f.properties.confidence = Some(d.confidence);
+                f.properties.corpus = d.corpus.clone();
+                results.push(f);
+            }
+
+            for e in r.explanations.iter().filter(|x| x.is_modified()) {
+                let changes: Vec<_> = e.changes.iter().filter(|x| !x.variable).collect();
+                let text = format!("Modified license {}: {} change{} outside of variable parts, \
+                                    first {} \"{}\"",
+                                   e.license,
+                                   changes.len(),
+                                   if changes.len() == 1 { "" } else { "s" },
+                                   changes[0].kind,
+                                   if changes[0].found.is_empty() {
+                                       &changes[0].expected
+                                   } else {
+                                       &changes[0].found
+                                   });
+                let region = Region::span(&changes[0].region);
+                results.push(finding(MODIFIED, root, &r.file, region, text));
+            }
+
+            for t in &r.tags {
+                let e = match t.expression {
+                    Some(ref e) if t.unknown.is_empty() => e,
+                    _ => {
+                        let reason = t.error.clone().unwrap_or_else(|| {
+                            format!("unknown license {}", t.unknown.join(", "))
+                        });
+                        results.push(finding(INVALID_TAG,
+                                             root,
+                                             &r.file,
+                                             Region::line(t.line),
+                                             format!("Invalid SPDX-License-Identifier {}: {}",
+                                                     t.value,
+                                                     reason)));
+                        continue;
+                    }
+                };
+
+                let declared = format!("{} declared by SPDX-License-Identifier", e);
+                let (rule, text) = match policy.check_expression(e) {
+                    Verdict::Allowed => (DETECTED, format!("License {}", declared)),
+                    Verdict::Denied => (DENIED, format!("Denied license {}", declared)),
+                    Verdict::NotAllowed => {
+                        (NOT_ALLOWED, format!("Not allowed license {}", declared))
+                    }
+                };
+                results.push(finding(rule, root, &r.file, Region::line(t.line), text));
+            }
+        }
+
+        for e in &result.errors {
+            results.push(finding(UNREADABLE,
+                                 root,
+                                 &e.file,
+                                 Region::line(1),
+                                 format!("File could not be scanned ({}): {}", e.kind, e.message)));
+        }
+
+        let rules = RULES.iter()
+                         .map(|&(id, name, description, level)| {
+                             Rule {
+                                 id: String::from(id),
+                                 name: String::from(name),
+                                 shortDescription: Message { text: String::from(description) },
+                                 defaultConfiguration: Configuration { level: String::from(level) },
+                             }
+                         })
+                         .collect();
+
+        // Single scanned file is located relative to its directory, trailing
+        // slash makes relative uris resolve inside the directory
+        let mut dir = Path::new(root).canonical_path();
+        if !dir.is_dir() {
+            dir.pop();
+        }
+        let mut base = dir.to_string_lossy().into_owned();
+        if !base.ends_with('/') {
+            base.push('/');
+        }
+        let mut bases = HashMap::new();
+        bases.insert(String::from(SRCROOT),
+                     BaseUri { uri: format!("file://{}", encode_path(&base)) });
+
+        Log {
+            runs: vec![Run {
+                           tool: Tool {
+                               driver: Driver {
+                                   name: String::from(TOOL_NAME),
+                                   version: String::from(TOOL_VERSION),
+                                   informationUri: String::from(INFORMATION_URI),
+                                   rules: rules,
+                               },
+                           },
+                           originalUriBaseIds: bases,
+                           results: results,
+                           properties: RunProperties {
+                               ssdeep_threshold: result.ssdeep_threshold,
+                           },
+                       }],
+        }
+    }
+
+    /// Format the log as SARIF JSON.
+    pub fn to_json(&self) -> String {
+        let pj = json::as_pretty_json(self).indent(3);
+        format!("{}", pj)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use detector::{Change, ChangeKind, Explanation, FileError};
+    use error::ErrorKind;
+    use output::tests::{ROOT, detection, file, result, span, tag};
+
+    /// Rule ids and levels of the findings of `log`.
+    fn findings(log: &Log) -> Vec<(&str, &str)> {
+        log.runs[0].results.iter().map(|x| (x.ruleId.as_str(), x.level.as_str())).collect()
+    }
+
+    #[test]
+    fn detected_licenses() {
+        let mit = detection("mit-license", Some("MIT"), 1.0);
+        let scan = result(vec![file("a.c", vec![mit], vec![]),
+                               file("b.c", vec![], vec![tag("MIT", &["MIT"])])]);
+        let log = Log::new(&scan, ROOT, &Policy::default());
+        assert_eq!(findings(&log), vec![("LIC001", "note"), ("LIC001", "note")]);
+        assert_eq!(log.runs[0].results[0].properties.corpus, Some(String::from("base")));
+    }
+
+    #[test]
+    fn policy_violations() {
+        // The MIT license is named after its file in the corpus
+        let mit = detection("mit-license", Some("MIT"), 1.0);
+        let scan = result(vec![file("a.c", vec![mit], vec![]),
+                               file("b.c", vec![detection("Own", None, 1.0)], vec![]),
+                               file("c.c", vec![], vec![tag("GPL-2.0", &["GPL-2.0"])])]);
+        let policy = Policy {
+            allowed: vec![String::from("MIT"), String::from("Own")],
+            denied: vec![String::from("GPL-2.0")],
+        };
+        assert_eq!(findings(&Log::new(&scan, ROOT, &policy)),
+                   vec![("LIC001", "note"), ("LIC001", "note"), ("LIC002", "error")]);
+
+        let policy = Policy {
+            allowed: vec![String::from("Own")],
+            denied: vec![String::from("mit")],
+        };
+        assert_eq!(findings(&Log::new(&scan, ROOT, &policy)),
+                   vec![("LIC002", "error"), ("LIC001", "note"), ("LIC003", "error")]);
+    }
+
+    #[test]
+    fn unknown_license_file() {
+        let scan = result(vec![file("LICENSE", vec![], vec![]), file("main.c", vec![], vec![])]);
+        assert_eq!(findings(&Log::new(&scan, ROOT, &Policy::default())),
+                   vec![("LIC004", "warning")]);
+    }
+
+    #[test]
+    fn unreadable_file() {
+        let mut scan = result(vec![]);
+        scan.errors.push(FileError {
+            file: format!("{}/data.bin", ROOT),
+            kind: ErrorKind::Encoding,
+            message: String::from("invalid encoding"),
+        });
+        assert_eq!(findings(&Log::new(&scan, ROOT, &Policy::default())),
+                   vec![("LIC005", "warning")]);
+    }
+
+    #[test]
+    fn invalid_tag() {
+        let mut unknown = tag("Own-License", &[]);
+        unknown.unknown.push(String::from("Own-License"));
+        let scan = result(vec![file("a.c", vec![], vec![tag("MIT OR", &["MIT"]), unknown])]);
+        assert_eq!(findings(&Log::new(&scan, ROOT, &Policy::default())),
+                   vec![("LIC006", "warning"), ("LIC006", "warning")]);
+    }
+
+    #[test]
+    fn modified_text() {
+        let change = |variable: bool| {
+            Change {
+                kind: ChangeKind::Substituted,
+                expected: String::from("holder"),
+                found: String::from("owner"),
+                region: span(),
+                variable: variable,
+            }
+        };
+        let explanation = |changes: Vec<Change>| {
+            Explanation {
+                license: String::from("MIT"),
+                kind: DetectionKind::Text,
+                region: span(),
+                matching: 160,
+                total: 161,
+                changes: changes,
+            }
+        };
+        let mut modified = file("LICENSE", vec![detection("MIT", Some("MIT"), 0.9)], vec![]);
+        modified.explanations.push(explanation(vec![change(true), change(false)]));
+        let mut variable = file("COPYING", vec![detection("MIT", Some("MIT"), 1.0)], vec![]);
+        variable.explanations.push(explanation(vec![change(true)]));
+
+        let log = Log::new(&result(vec![modified, variable]), ROOT, &Policy::default());
+        assert_eq!(findings(&log),
+                   vec![("LIC001", "note"), ("LIC007", "warning"), ("LIC001", "note")]);
+        assert!(log.runs[0].results[1].message.text.contains("1 change outside"));
+    }
+
+    #[test]
+    fn encode_path_segments() {
+        assert_eq!(encode_path("src/main.rs"), "src/main.rs");
+        assert_eq!(encode_path("my docs/100%#1.txt"), "my%20docs/100%25%231.txt");
+        assert_eq!(encode_path("/tmp/licença"), "/tmp/licen%C3%A7a");
+    }
+}
@@ -0,0 +1,369 @@
+#![allow(non_snake_case)]
+use std::collections::BTreeMap;
+
+use rustc_serialize::{Encodable, Encoder};
+use rustc_serialize::json;
+
+use corpus::TextsData;
+use detector::ScanResult;
+use output::{self, TOOL_NAME, TOOL_VERSION};
+use spdx::expression::LICENSE_REF;
+
+/// Version of the SPDX specification the documents follow.
+pub static SPDX_VERSION: &'static str = "SPDX-2.3";
+
+/// License of SPDX document metadata mandated by the specification.
+static DATA_LICENSE: &'static str = "CC0-1.0";
+
+/// Value for fields liceum doesn't have an opinion on.
+static NOASSERTION: &'static str = "NOASSERTION";
+
+/// Predicate determining whether `c` may appear in SPDX identifiers.
+fn is_id_char(c: char) -> bool {
+    match c {
+        'a'...'z' | 'A'...'Z' | '0'...'9' | '.' | '-' => true,
+        _ => false,
+    }
+}
+
+/// SPDX identifier for license `name`, its `spdx_id` when it is on the
+/// SPDX license list and a `LicenseRef-` otherwise.
+pub fn license_id(name: &str, spdx_id: Option<&str>) -> String {
+    if let Some(id) = spdx_id {
+        return String::from(id);
+    }
+
+    let sanitized: String = name.chars().map(|c| if is_id_char(c) { c } else { '-' }).collect();
+    format!("{}{}", LICENSE_REF, sanitized)
+}
+
+#[derive(RustcEncodable)]
+struct CreationInfo {
+    creators: Vec<String>,
+    created: String,
+    /// Settings of the scan
+    comment: String,
+}
+
+#[derive(RustcEncodable)]
+struct Checksum {
+    algorithm: String,
+    checksumValue: String,
+}
+
+/// `File` entry of the document
+struct FileInfo {
+    fileName: String,
+    SPDXID: String,
+    checksums: Vec<Checksum>,
+    licenseConcluded: String,
+    licenseInfoInFiles: Vec<String>,
+    copyrightText: String,
+    /// Corpora the detected licenses come from, if known
+    comment: Option<String>,
+}
+
+impl Encodable for FileInfo {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("FileInfo", 7, |s| {
+            try!(s.emit_struct_field("fileName", 0, |s| self.fileName.encode(s)));
+            try!(s.emit_struct_field("SPDXID", 1, |s| self.SPDXID.encode(s)));
+            try!(s.emit_struct_field("checksums", 2, |s| self.checksums.encode(s)));
+            try!(s.emit_struct_field("licenseConcluded", 3, |s| self.licenseConcluded.encode(s)));
+            try!(s.emit_struct_field("licenseInfoInFiles",
+                                     4,
+                                     |s| self.licenseInfoInFiles.encode(s)));
+            try!(s.emit_struct_field("copyrightText", 5, |s| self.copyrightText.encode(s)));
+            if let Some(ref comment) = self.comment {
+                try!(s.emit_struct_field("comment", 6, |s| comment.encode(s)));
+            }
+            Ok(())
+        })
+    }
+}
+
+/// License referenced as `LicenseRef-` in `licenseInfoInFiles`
+#[derive(RustcEncodable)]
+struct ExtractedLicense {
+    licenseId: String,
+    name: String,
+    extractedText: String,
+}
+
+#[derive(RustcEncodable)]
+struct Relationship {
+    spdxElementId: String,
+    relationshipType: String,
+    relatedSpdxElement: String,
+}
+
+/// SPDX 2.3 document describing every scanned file, serializable both as
+/// tag-value and JSON.
+#[derive(RustcEncodable)]
+pub struct Document {
+    spdxVersion: String,
+    dataLicense: String,
+    SPDXID: String,
+    name: String,
+    documentNamespace: String,
+    creationInfo: CreationInfo,
+    files: Vec<FileInfo>,
+    hasExtractedLicensingInfos: Vec<ExtractedLicense>,
+    relationships: Vec<Relationship>,
+}
+
+impl Document {
+    /// Create document from `result` of scanning `root`, file names are
+    /// relative to `root`. Licenses off the SPDX license list are extracted
+    /// with their text from `texts` when the corpus has it.
+    pub fn new(result: &ScanResult, root: &str, texts: &TextsData) -> Document {
+        let name = output::root_name(root);
+        let created = output::timestamp();
+        // LicenseRef id: (license name, extracted text)
+        let mut refs: BTreeMap<String, (String, String)> = BTreeMap::new();
+        let mut files: Vec<FileInfo> = Vec::new();
+
+        for (i, r) in result.files.iter().enumerate() {
+            let mut licenses: Vec<String> = Vec::new();
+            let mut origins: Vec<String> = Vec::new();
+            for d in &r.detections {
+                if let Some(ref corpus) = d.corpus {
+                    origins.push(format!("{} {} from corpus {}", d.kind, d.license, corpus));
+                }
+                let id = license_id(&d.license, d.spdx_id.as_ref().map(|x| x.as_str()));
+                if id.starts_with(LICENSE_REF) {
+                    let text = match texts.licenses.get(&d.license) {
+                        Some(t) => t.text.clone(),
+                        None => format!("License detected by {} as {}", TOOL_NAME, d.license),
+                    };
+                    refs.insert(id.clone(), (d.license.clone(), text));
+                }
+                licenses.push(id);
+            }
+            // Licenses declared by valid SPDX-License-Identifier tags
+            for e in r.tags.iter().filter(|x| x.is_valid()).filter_map(|x| x.expression.as_ref()) {
+                for l in e.licenses() {
+                    if l.id.starts_with(LICENSE_REF) {
+                        let text = format!("License declared by SPDX-License-Identifier as {}",
+                                           l.id);
+                        refs.entry(l.id.clone()).or_insert((l.id.clone(), text));
+                    }
+                    let id = l.to_string();
+                    if !licenses.contains(&id) {
+                        licenses.push(id);
+                    }
+                }
+            }
+            if licenses.is_empty() {
+                licenses.push(String::from(NOASSERTION));
+            }
+
+            files.push(FileInfo {
+                fileName: format!("./{}", output::relative_path(root, &r.file)),
+                SPDXID: format!("SPDXRef-File-{}", i + 1),
+                checksums: vec![Checksum {
+                                    algorithm: String::from("SHA1"),
+                                    checksumValue: r.sha1.clone(),
+                                }],
+                licenseConcluded: String::from(NOASSERTION),
+                licenseInfoInFiles: licenses,
+                copyrightText: String::from(NOASSERTION),
+                comment: if origins.is_empty() {
+                    None
+                } else {
+                    Some(format!("Detected by {}: {}", TOOL_NAME, origins.join(", ")))
+                },
+            });
+        }
+
+        let namespace = format!("https://spdx.org/spdxdocs/{}-{}-{}",
+                                TOOL_NAME,
+                                name,
+                                output::scan_digest(result, &created));
+
+        let extracted = refs.into_iter()
+                            .map(|(id, (name, text))| {
+                                ExtractedLicense {
+                                    licenseId: id,
+                                    name: name,
+                                    extractedText: text,
+                                }
+                            })
+                            .collect();
+
+        let relationships = files.iter()
+                                 .map(|x| {
+                                     Relationship {
+                                         spdxElementId: String::from("SPDXRef-DOCUMENT"),
+                                         relationshipType: String::from("DESCRIBES"),
+                                         relatedSpdxElement: x.SPDXID.clone(),
+                                     }
+                                 })
+                                 .collect();
+
+        Document {
+            spdxVersion: String::from(SPDX_VERSION),
+            dataLicense: String::from(DATA_LICENSE),
+            SPDXID: String::from("SPDXRef-DOCUMENT"),
+            name: name,
+            documentNamespace: namespace,
+            creationInfo: CreationInfo {
+                creators: vec![format!("Tool: {}-{}", TOOL_NAME, TOOL_VERSION)],
+                created: created,
+                comment: format!("ssdeep similarity threshold: {}", result.ssdeep_threshold),
+            },
+            files: files,
+            hasExtractedLicensingInfos: extracted,
+            relationships: relationships,
+        }
+    }
+
+    /// Format the document in the SPDX tag-value serialization.
+    pub fn to_tag_value(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("SPDXVersion: {}\n", self.spdxVersion));
+        out.push_str(&format!("DataLicense: {}\n", self.dataLicense));
+        out.push_str(&format!("SPDXID: {}\n", self.SPDXID));
+        out.push_str(&format!("DocumentName: {}\n", self.name));
+        out.push_str(&format!("DocumentNamespace: {}\n", self.documentNamespace));
+        for c in &self.creationInfo.creators {
+            out.push_str(&format!("Creator: {}\n", c));
+        }
+        out.push_str(&format!("Created: {}\n", self.creationInfo.created));
+        out.push_str(&format!("CreatorComment: <text>{}</text>\n", self.creationInfo.comment));
+
+        for f in &self.files {
+            out.push_str("\n## File\n\n");
+            out.push_str(&format!("FileName: {}\n", f.fileName));
+            out.push_str(&format!("SPDXID: {}\n", f.SPDXID));
+            for c in &f.checksums {
+                out.push_str(&format!("FileChecksum: {}: {}\n", c.algorithm, c.checksumValue));
+            }
+            out.push_str(&format!("LicenseConcluded: {}\n", f.licenseConcluded));
+            for l in &f.licenseInfoInFiles {
+                out.push_str(&format!("LicenseInfoInFile: {}\n", l));
+            }
+            out.push_str(&format!("FileCopyrightText: {}\n", f.copyrightText));
+            if let Some(ref comment) = f.comment {
+                out.push_str(&format!("FileComment: <text>{}</text>\n", comment));
+            }
+        }
+
+        for e in &self.hasExtractedLicensingInfos {
+            out.push_str("\n## Other Licensing Information Detected\n\n");
+            out.push_str(&format!("LicenseID: {}\n", e.licenseId));
+            out.push_str(&format!("ExtractedText: <text>{}</text>\n", e.extractedText));
+            out.push_str(&format!("LicenseName: {}\n", e.name));
+        }
+
+        if !self.relationships.is_empty() {
+            out.push_str("\n## Relationships\n\n");
+        }
+        for r in &self.relationships {
+            out.push_str(&format!("Relationship: {} {} {}\n",
+                                  r.spdxElementId,
+                                  r.relationshipType,
+                                  r.relatedSpdxElement));
+        }
+
+        out
+    }
+
+    /// Format the document in the SPDX JSON serialization.
+    pub fn to_json(&self) -> String {
+        let pj = json::as_pretty_json(self).indent(3);
+        format!("{}", pj)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_serialize::json::Json;
+
+    use super::*;
+    use corpus::{LicenseText, TextsData};
+    use output::tests::{ROOT, detection, file, result, tag};
+
+    /// Result of a scan with licenses on and off the SPDX license list and
+    /// the texts of the corpus.
+    fn scan() -> (ScanResult, TextsData) {
+        let mut texts = TextsData::default();
+        texts.licenses.insert(String::from("Own License"),
+                              LicenseText {
+                                  text: String::from("Do what you want."),
+                                  template: None,
+                                  ngrams: None,
+                              });
+        let result = result(vec![file("LICENSE", vec![detection("Own License", None, 1.0)], vec![]),
+                                 file("a.c",
+                                      vec![detection("MIT", Some("MIT"), 0.9)],
+                                      vec![tag("MIT OR LicenseRef-Other", &["MIT"])]),
+                                 file("b.c", vec![], vec![])]);
+        (result, texts)
+    }
+
+    #[test]
+    fn listed_license_id() {
+        assert_eq!(license_id("MIT", Some("MIT")), "MIT");
+        assert_eq!(license_id("mit", None), "LicenseRef-mit");
+        assert_eq!(license_id("GPL v2 (custom)", None), "LicenseRef-GPL-v2--custom-");
+    }
+
+    #[test]
+    fn tag_value_document() {
+        let (result, texts) = scan();
+        let doc = Document::new(&result, ROOT, &texts).to_tag_value();
+        let files: Vec<&str> = doc.split("## File").skip(1).collect();
+        assert_eq!(files.len(), 3);
+        assert!(files[0].contains("FileName: ./LICENSE\n"));
+        assert!(files[0].contains("LicenseConcluded: NOASSERTION\n\
+                                   LicenseInfoInFile: LicenseRef-Own-License\n"));
+        assert!(files[0].contains("FileComment: <text>Detected by liceum: text Own License from \
+                                   corpus base</text>"));
+        assert!(files[1].contains("LicenseInfoInFile: MIT\nLicenseInfoInFile: LicenseRef-Other\n"));
+        assert!(files[2].contains("LicenseInfoInFile: NOASSERTION\n"));
+        assert!(!files[2].contains("FileComment"));
+
+        assert!(doc.contains("LicenseID: LicenseRef-Own-License\n\
+                              ExtractedText: <text>Do what you want.</text>\n\
+                              LicenseName: Own License\n"));
+        assert!(doc.contains("LicenseID: LicenseRef-Other\n\
+                              ExtractedText: <text>License declared by SPDX-License-Identifier \
+                              as LicenseRef-Other</text>\n"));
+        assert!(!doc.contains("LicenseID: MIT"));
+    }
+
+    #[test]
+    fn json_document() {
+        let (result, texts) = scan();
+        let doc = Json::from_str(&Document::new(&result, ROOT, &texts).to_json()).unwrap();
+        let strings = |x: &Json| -> Vec<String> {
+            x.as_array().unwrap().iter().map(|s| String::from(s.as_string().unwrap())).collect()
+        };
+
+        let files = doc["files"].as_array().unwrap();
+        let conclusions: Vec<(String, Vec<String>)> =
+            files.iter()
+                 .map(|f| (String::from(f["licenseConcluded"].as_string().unwrap()),
+                           strings(&f["licenseInfoInFiles"])))
+                 .collect();
+        let noassertion = String::from("NOASSERTION");
+        assert_eq!(conclusions,
+                   vec![(noassertion.clone(), vec![String::from("LicenseRef-Own-License")]),
+                        (noassertion.clone(),
+                         vec![String::from("MIT"), String::from("LicenseRef-Other")]),
+                        (noassertion.clone(), vec![noassertion.clone()])]);
+
+        let extracted: Vec<(&str, &str)> =
+            doc["hasExtractedLicensingInfos"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|x| {
+                    (x["licenseId"].as_string().unwrap(), x["extractedText"].as_string().unwrap())
+                })
+                .collect();
+        assert_eq!(extracted[1], ("LicenseRef-Own-License", "Do what you want."));
+        assert_eq!(extracted[0].0, "LicenseRef-Other");
+    }
+}
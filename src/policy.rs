@@ -0,0 +1,77 @@
+use spdx::expression::Expression;
+
+/// License policy deciding which detected licenses are acceptable.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    /// If not empty only these licenses are acceptable
+    pub allowed: Vec<String>,
+    /// Licenses which are never acceptable
+    pub denied: Vec<String>,
+}
+
+/// Outcome of checking a license against `Policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// License is acceptable
+    Allowed,
+    /// License is on the deny list
+    Denied,
+    /// Allow list is in use and the license isn't on it
+    NotAllowed,
+}
+
+impl Verdict {
+    /// Severity of the verdict, higher is worse.
+    fn severity(&self) -> u8 {
+        match *self {
+            Verdict::Allowed => 0,
+            Verdict::NotAllowed => 1,
+            Verdict::Denied => 2,
+        }
+    }
+}
+
+impl Policy {
+    /// Parse comma separated list of license names.
+    pub fn parse_list(spec: &str) -> Vec<String> {
+        spec.split(',')
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /// Predicate determining whether the policy restricts anything at all.
+    pub fn is_empty(&self) -> bool {
+        self.allowed.is_empty() && self.denied.is_empty()
+    }
+
+    /// Check `license` against the policy, names are compared without
+    /// regard to case.
+    pub fn check(&self, license: &str) -> Verdict {
+        let matches = |x: &String| x.to_lowercase() == license.to_lowercase();
+        if self.denied.iter().any(&matches) {
+            Verdict::Denied
+        } else if !self.allowed.is_empty() && !self.allowed.iter().any(&matches) {
+            Verdict::NotAllowed
+        } else {
+            Verdict::Allowed
+        }
+    }
+
+    /// Check license `expression` against the policy, `OR` is acceptable if
+    /// any of its alternatives is and `AND` only if all of its parts are.
+    pub fn check_expression(&self, expression: &Expression) -> Verdict {
+        match *expression {
+            Expression::License(ref x) | Expression::With(ref x, _) => self.check(&x.id),
+            Expression::And(ref a, ref b) => {
+                let (a, b) = (self.check_expression(a), self.check_expression(b));
+                if a.severity() >= b.severity() { a } else { b }
+            }
+            Expression::Or(ref a, ref b) => {
+                let (a, b) = (self.check_expression(a), self.check_expression(b));
+                if a.severity() <= b.severity() { a } else { b }
+            }
+        }
+    }
+}
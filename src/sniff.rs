@@ -0,0 +1,274 @@
+use std::fs::File;
+use std::io::{Read, Error};
+use std::{char, str};
+
+/// Number of bytes from the beginning of a file used for classification.
+const SAMPLE_SIZE: usize = 8192;
+
+/// Maximum ratio of control characters tolerated in a text file.
+const MAX_CONTROL_RATIO: f32 = 0.05;
+
+/// Magic numbers of common binary formats and the offset they're found at.
+static BINARY_MAGIC: &'static [(usize, &'static [u8])] = &[
+    (0, b"\x7fELF"),                   // ELF executables and libraries
+    (0, b"MZ"),                        // PE/DOS executables
+    (0, b"\xfe\xed\xfa\xce"),          // Mach-O 32-bit
+    (0, b"\xfe\xed\xfa\xcf"),          // Mach-O 64-bit
+    (0, b"\xce\xfa\xed\xfe"),          // Mach-O 32-bit, reverse byte order
+    (0, b"\xcf\xfa\xed\xfe"),          // Mach-O 64-bit, reverse byte order
+    (0, b"\xca\xfe\xba\xbe"),          // Java class, Mach-O fat binary
+    (0, b"\x00asm"),                   // WebAssembly
+    (0, b"!<arch>\n"),                 // ar archives, static libraries
+    (0, b"PK\x03\x04"),                // zip, jar, docx, ...
+    (0, b"PK\x05\x06"),                // empty zip
+    (0, b"\x1f\x8b"),                  // gzip
+    (0, b"BZh"),                       // bzip2
+    (0, b"\xfd7zXZ\x00"),              // xz
+    (0, b"7z\xbc\xaf\x27\x1c"),        // 7-zip
+    (0, b"\x28\xb5\x2f\xfd"),          // zstd
+    (0, b"Rar!\x1a\x07"),              // rar
+    (257, b"ustar"),                   // tar
+    (0, b"%PDF-"),                     // PDF
+    (0, b"%!PS"),                      // PostScript
+    (0, b"\x89PNG\r\n\x1a\n"),         // PNG
+    (0, b"GIF87a"),                    // GIF
+    (0, b"GIF89a"),                    // GIF
+    (0, b"\xff\xd8\xff"),              // JPEG
+    (0, b"RIFF"),                      // WAV, AVI, WebP
+    (0, b"OggS"),                      // Ogg
+    (0, b"fLaC"),                      // FLAC
+    (0, b"ID3"),                       // MP3
+    (0, b"\x00\x00\x01\x00"),          // ICO
+    (0, b"SQLite format 3\x00"),       // SQLite database
+    (0, b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1"), // OLE2, legacy MS Office
+];
+
+/// Content classification of a file or a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    /// No content at all
+    Empty,
+    /// Human readable text in any of the encodings `decode` reads
+    Text,
+    /// Anything else
+    Binary,
+}
+
+/// Check whether `data` starts with a byte order mark of a unicode encoding.
+fn has_bom(data: &[u8]) -> bool {
+    data.starts_with(b"\xef\xbb\xbf") || data.starts_with(b"\x00\x00\xfe\xff") ||
+    data.starts_with(b"\xff\xfe") || data.starts_with(b"\xfe\xff")
+}
+
+/// Check whether `data` starts with any of the known binary magic numbers.
+fn has_binary_magic(data: &[u8]) -> bool {
+    BINARY_MAGIC.iter().any(|&(offset, magic)| {
+        data.len() >= offset + magic.len() && &data[offset..offset + magic.len()] == magic
+    })
+}
+
+/// Check whether `data` is valid UTF-8, allowing for a multi-byte sequence
+/// cut in half at the end of the sample.
+fn is_utf8(data: &[u8]) -> bool {
+    match str::from_utf8(data) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && data.len() == SAMPLE_SIZE,
+    }
+}
+
+/// Classify `data` as text or binary content.
+///
+/// Unicode byte order marks always mean text, known magic numbers and NUL
+/// bytes always mean binary. Everything else is text if it's either valid
+/// UTF-8 or a single byte encoding without too many control characters.
+pub fn classify(data: &[u8]) -> ContentType {
+    if data.is_empty() {
+        return ContentType::Empty;
+    }
+
+    let sample = &data[..if data.len() > SAMPLE_SIZE { SAMPLE_SIZE } else { data.len() }];
+    if has_bom(sample) {
+        return ContentType::Text;
+    }
+
+    if has_binary_magic(sample) || sample.contains(&0) {
+        return ContentType::Binary;
+    }
+
+    // Control characters other than the usual whitespace, form feed and escape
+    let control = sample.iter()
+                        .filter(|&&c| {
+                            (c < 0x20 && c != b'\t' && c != b'\n' && c != b'\r' && c != 0x0c &&
+                             c != 0x1b) || c == 0x7f
+                        })
+                        .count();
+    if control as f32 / sample.len() as f32 > MAX_CONTROL_RATIO {
+        return ContentType::Binary;
+    }
+
+    if is_utf8(sample) {
+        return ContentType::Text;
+    }
+
+    // Legacy 8-bit encodings (latin-1 and friends) rarely use the C1 range
+    let c1 = sample.iter().filter(|&&c| c >= 0x80 && c < 0xa0).count();
+    if c1 as f32 / sample.len() as f32 > MAX_CONTROL_RATIO {
+        ContentType::Binary
+    } else {
+        ContentType::Text
+    }
+}
+
+/// Text of UTF-16 code units of `data` in the given byte order.
+fn decode_utf16(data: &[u8], big_endian: bool) -> Option<String> {
+    if data.len() % 2 != 0 {
+        return None;
+    }
+    let units: Vec<u16> = data.chunks(2)
+                              .map(|x| {
+                                  if big_endian {
+                                      (x[0] as u16) << 8 | x[1] as u16
+                                  } else {
+                                      (x[1] as u16) << 8 | x[0] as u16
+                                  }
+                              })
+                              .collect();
+    String::from_utf16(&units).ok()
+}
+
+/// Text of UTF-32 code points of `data` in the given byte order.
+fn decode_utf32(data: &[u8], big_endian: bool) -> Option<String> {
+    if data.len() % 4 != 0 {
+        return None;
+    }
+    data.chunks(4)
+        .map(|x| {
+            let bytes = if big_endian {
+                [x[0], x[1], x[2], x[3]]
+            } else {
+                [x[3], x[2], x[1], x[0]]
+            };
+            let code = bytes.iter().fold(0u32, |acc, &b| acc << 8 | b as u32);
+            char::from_u32(code)
+        })
+        .collect()
+}
+
+/// Decode `data` in any of the encodings `classify` takes for text:
+/// UTF-16 and UTF-32 with a byte order mark, which is dropped, UTF-8 and
+/// otherwise latin-1. `None` if the text isn't valid in the encoding of
+/// its byte order mark.
+pub fn decode(data: &[u8]) -> Option<String> {
+    if data.starts_with(b"\x00\x00\xfe\xff") {
+        return decode_utf32(&data[4..], true);
+    }
+    if data.starts_with(b"\xff\xfe\x00\x00") {
+        return decode_utf32(&data[4..], false);
+    }
+    if data.starts_with(b"\xfe\xff") {
+        return decode_utf16(&data[2..], true);
+    }
+    if data.starts_with(b"\xff\xfe") {
+        return decode_utf16(&data[2..], false);
+    }
+
+    match str::from_utf8(data) {
+        Ok(x) => Some(String::from(x)),
+        Err(_) => Some(data.iter().map(|&c| c as char).collect()),
+    }
+}
+
+/// Classify the content of `file` by looking at its first few kilobytes.
+pub fn classify_file(file: &str) -> Result<ContentType, Error> {
+    let f = try!(File::open(file));
+    let mut data: Vec<u8> = Vec::with_capacity(SAMPLE_SIZE);
+    try!(f.take(SAMPLE_SIZE as u64).read_to_end(&mut data));
+    Ok(classify(&data))
+}
+
+/// Predicate determining whether `file` is a non-empty text file, unreadable
+/// files are not considered text.
+pub fn is_text_file(file: &str) -> bool {
+    classify_file(file).map(|x| x == ContentType::Text).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "ā" is valid UTF-8 made of a lead byte and a C1 control byte
+    fn macrons(prefix: &str) -> Vec<u8> {
+        let mut data = prefix.as_bytes().to_vec();
+        while data.len() < SAMPLE_SIZE + 16 {
+            data.extend_from_slice("ā".as_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn classify_empty() {
+        assert_eq!(classify(b""), ContentType::Empty);
+    }
+
+    #[test]
+    fn classify_bom() {
+        assert_eq!(classify(b"\xef\xbb\xbfMIT License"), ContentType::Text);
+        assert_eq!(classify(b"\xff\xfeM\x00I\x00T\x00"), ContentType::Text);
+    }
+
+    #[test]
+    fn classify_nul() {
+        assert_eq!(classify(b"MIT\x00License"), ContentType::Binary);
+    }
+
+    #[test]
+    fn classify_magic() {
+        assert_eq!(classify(b"\x7fELF\x02\x01\x01"), ContentType::Binary);
+        assert_eq!(classify(b"PK\x03\x04LICENSE"), ContentType::Binary);
+
+        let mut tar = vec![b'a'; 257];
+        tar.extend_from_slice(b"ustar  ");
+        assert_eq!(classify(&tar), ContentType::Binary);
+        assert_eq!(classify(&tar[1..]), ContentType::Text);
+    }
+
+    #[test]
+    fn classify_control_ratio() {
+        let mut data = vec![b'a'; 100];
+        data[0] = 0x01;
+        assert_eq!(classify(&data), ContentType::Text);
+        for c in data.iter_mut().take(10) {
+            *c = 0x01;
+        }
+        assert_eq!(classify(&data), ContentType::Binary);
+    }
+
+    #[test]
+    fn classify_latin1() {
+        assert_eq!(classify(b"Copyright \xa9 2016 J\xfcrgen"), ContentType::Text);
+        assert_eq!(classify(b"\x81\x82\x83\x84 data"), ContentType::Binary);
+    }
+
+    #[test]
+    fn classify_cut_sequence() {
+        // The sample ends between the two bytes of a character
+        let data = macrons("a");
+        assert_eq!(data[SAMPLE_SIZE - 1], 0xc4);
+        assert_eq!(classify(&data), ContentType::Text);
+
+        let mut invalid = data.clone();
+        invalid[SAMPLE_SIZE - 3] = 0xff;
+        assert_eq!(classify(&invalid), ContentType::Binary);
+    }
+
+    #[test]
+    fn decode_encodings() {
+        assert_eq!(decode(b"\xef\xbb\xbfMIT").unwrap(), "\u{feff}MIT");
+        assert_eq!(decode(b"\xff\xfeM\x00\xe9\x00").unwrap(), "Mé");
+        assert_eq!(decode(b"\xfe\xff\x00M\x00\xe9").unwrap(), "Mé");
+        assert_eq!(decode(b"\xff\xfe\x00\x00M\x00\x00\x00").unwrap(), "M");
+        assert_eq!(decode(b"\x00\x00\xfe\xff\x00\x00\x00M").unwrap(), "M");
+        assert_eq!(decode(b"J\xfcrgen").unwrap(), "Jürgen");
+        assert_eq!(decode(b"\xff\xfeM"), None);
+    }
+}
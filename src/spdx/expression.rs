@@ -0,0 +1,358 @@
+use std::fmt;
+
+use regex::Regex;
+
+/// Prefix of license identifiers defined by the document using them.
+pub static LICENSE_REF: &'static str = "LicenseRef-";
+
+/// Prefix of references to licenses defined by other documents.
+pub static DOCUMENT_REF: &'static str = "DocumentRef-";
+
+/// Single license of an expression, `or_later` stands for the `+` suffix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleExpression {
+    pub id: String,
+    pub or_later: bool,
+}
+
+/// Parsed SPDX license expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expression {
+    License(SimpleExpression),
+    /// License with an exception, `GPL-2.0-only WITH Classpath-exception-2.0`
+    With(SimpleExpression, String),
+    /// Both licenses apply
+    And(Box<Expression>, Box<Expression>),
+    /// Either of the licenses may be chosen
+    Or(Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    With,
+    Id(String),
+}
+
+/// Predicate determining whether `c` may appear in SPDX identifiers.
+fn is_id_char(c: char) -> bool {
+    match c {
+        'a'...'z' | 'A'...'Z' | '0'...'9' | '.' | '-' => true,
+        _ => false,
+    }
+}
+
+/// Predicate determining whether `id` names a license defined outside of
+/// the SPDX license list.
+pub fn is_license_ref(id: &str) -> bool {
+    id.starts_with(LICENSE_REF) || id.starts_with(DOCUMENT_REF)
+}
+
+/// Split `text` into tokens, operators are accepted either all upper or
+/// all lower case.
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '(' || c == ')' {
+            chars.next();
+            tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            continue;
+        }
+
+        let mut word = String::new();
+        while let Some(&c) = chars.peek() {
+            if !is_id_char(c) && c != ':' && c != '+' {
+                break;
+            }
+            word.push(c);
+            chars.next();
+        }
+        if word.is_empty() {
+            return Err(format!("Unexpected character '{}'", c));
+        }
+
+        tokens.push(match word.as_str() {
+            "AND" | "and" => Token::And,
+            "OR" | "or" => Token::Or,
+            "WITH" | "with" => Token::With,
+            _ => Token::Id(word),
+        });
+    }
+    Ok(tokens)
+}
+
+/// Check that `id` is a valid license or exception identifier, `+` is
+/// already stripped.
+fn check_id(id: &str) -> Result<(), String> {
+    let simple = |x: &str| !x.is_empty() && x.chars().all(is_id_char);
+    let valid = if id.starts_with(DOCUMENT_REF) {
+        let mut parts = id.splitn(2, ':');
+        let (doc, license) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        simple(doc) && doc.len() > DOCUMENT_REF.len() && license.starts_with(LICENSE_REF) &&
+        license.len() > LICENSE_REF.len() && simple(license)
+    } else if id.starts_with(LICENSE_REF) {
+        id.len() > LICENSE_REF.len() && simple(id)
+    } else {
+        simple(id)
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid identifier '{}'", id))
+    }
+}
+
+/// Recursive descent parser, operators bind from the tightest `WITH` over
+/// `AND` to `OR`.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn or(&mut self) -> Result<Expression, String> {
+        let mut left = try!(self.and());
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let right = try!(self.and());
+            left = Expression::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expression, String> {
+        let mut left = try!(self.with());
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            let right = try!(self.with());
+            left = Expression::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn with(&mut self) -> Result<Expression, String> {
+        let license = match self.next() {
+            Some(Token::Open) => {
+                let inner = try!(self.or());
+                if self.next() != Some(Token::Close) {
+                    return Err(String::from("Missing closing parenthesis"));
+                }
+                return Ok(inner);
+            }
+            Some(Token::Id(id)) => try!(simple_expression(&id)),
+            Some(t) => return Err(format!("Expected license identifier, found {:?}", t)),
+            None => return Err(String::from("Unexpected end of expression")),
+        };
+
+        if self.peek() != Some(&Token::With) {
+            return Ok(Expression::License(license));
+        }
+        self.pos += 1;
+        match self.next() {
+            Some(Token::Id(exception)) => {
+                try!(check_id(&exception));
+                Ok(Expression::With(license, exception))
+            }
+            _ => Err(String::from("Expected exception identifier after WITH")),
+        }
+    }
+}
+
+/// Parse license identifier `id` with an optional `+` suffix.
+fn simple_expression(id: &str) -> Result<SimpleExpression, String> {
+    let or_later = id.ends_with('+');
+    let id = id.trim_right_matches('+');
+    try!(check_id(id));
+    if or_later && is_license_ref(id) {
+        return Err(format!("'+' can't be applied to '{}'", id));
+    }
+    Ok(SimpleExpression {
+        id: String::from(id),
+        or_later: or_later,
+    })
+}
+
+impl Expression {
+    /// Parse SPDX license expression `text`.
+    pub fn parse(text: &str) -> Result<Expression, String> {
+        let mut parser = Parser {
+            tokens: try!(tokenize(text)),
+            pos: 0,
+        };
+        let expression = try!(parser.or());
+        match parser.peek() {
+            None => Ok(expression),
+            Some(t) => Err(format!("Unexpected {:?}", t)),
+        }
+    }
+
+    /// All licenses of the expression in order of appearance.
+    pub fn licenses(&self) -> Vec<&SimpleExpression> {
+        match *self {
+            Expression::License(ref x) | Expression::With(ref x, _) => vec![x],
+            Expression::And(ref a, ref b) | Expression::Or(ref a, ref b) => {
+                let mut out = a.licenses();
+                out.extend(b.licenses());
+                out
+            }
+        }
+    }
+}
+
+impl fmt::Display for SimpleExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.id, if self.or_later { "+" } else { "" })
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expression::License(ref x) => write!(f, "{}", x),
+            Expression::With(ref x, ref exception) => write!(f, "{} WITH {}", x, exception),
+            Expression::And(ref a, ref b) => {
+                // OR binds looser than AND
+                let operand = |x: &Expression| {
+                    match *x {
+                        Expression::Or(..) => format!("({})", x),
+                        _ => x.to_string(),
+                    }
+                };
+                write!(f, "{} AND {}", operand(a), operand(b))
+            }
+            Expression::Or(ref a, ref b) => write!(f, "{} OR {}", a, b),
+        }
+    }
+}
+
+/// Patterns locating tags, compiled once.
+struct Patterns {
+    tag: Regex,
+    // Comment terminators left on the line of the tag
+    end: Regex,
+}
+
+impl Patterns {
+    fn new() -> Patterns {
+        Patterns {
+            tag: Regex::new(concat!(r"^\s*(?:(?://+!?|#+|/\*+!?|\*+|--+|;+|%+|!+|<!--|\{-|\(\*|",
+                                    r"\.\.|(?i:rem|dnl))\s*)?SPDX-License-Identifier:(.*)$"))
+                     .unwrap(),
+            end: Regex::new(r"\s*(?:\*/|-->|-\}|\*\)|#\})\s*$").unwrap(),
+        }
+    }
+}
+
+// Every thread compiles the patterns once, on first use
+thread_local!(static PATTERNS: Patterns = Patterns::new());
+
+/// Find `SPDX-License-Identifier` tags at the start of comments in `text`,
+/// returned as (line starting at 1, value) pairs.
+pub fn find_tags(text: &str) -> Vec<(usize, String)> {
+    PATTERNS.with(|p| {
+        let mut tags: Vec<(usize, String)> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            if let Some(value) = p.tag.captures(line).and_then(|x| x.at(1)) {
+                let value = p.end.replace(value, "");
+                if !value.trim().is_empty() {
+                    tags.push((i + 1, String::from(value.trim())));
+                }
+            }
+        }
+        tags
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> String {
+        Expression::parse(text).unwrap().to_string()
+    }
+
+    #[test]
+    fn parse_simple_expressions() {
+        assert_eq!(Expression::parse("MIT").unwrap(),
+                   Expression::License(SimpleExpression {
+                       id: String::from("MIT"),
+                       or_later: false,
+                   }));
+        let e = Expression::parse("GPL-2.0+ WITH Classpath-exception-2.0").unwrap();
+        let licenses = e.licenses();
+        assert_eq!(licenses.len(), 1);
+        assert!(licenses[0].or_later);
+        assert_eq!(e.to_string(), "GPL-2.0+ WITH Classpath-exception-2.0");
+        assert_eq!(parse("LicenseRef-foo or DocumentRef-x:LicenseRef-bar"),
+                   "LicenseRef-foo OR DocumentRef-x:LicenseRef-bar");
+    }
+
+    #[test]
+    fn operator_precedence() {
+        // AND binds tighter than OR, WITH tighter than AND
+        match Expression::parse("MIT OR Apache-2.0 AND BSD-3-Clause").unwrap() {
+            Expression::Or(a, b) => {
+                assert_eq!(a.to_string(), "MIT");
+                assert_eq!(b.to_string(), "Apache-2.0 AND BSD-3-Clause");
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+        match Expression::parse("MIT AND GPL-2.0-only WITH Autoconf-exception-2.0").unwrap() {
+            Expression::And(_, b) => {
+                assert_eq!(b.to_string(), "GPL-2.0-only WITH Autoconf-exception-2.0")
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+        assert_eq!(parse("(MIT OR Apache-2.0) AND BSD-3-Clause"),
+                   "(MIT OR Apache-2.0) AND BSD-3-Clause");
+        assert_eq!(parse("((MIT))"), "MIT");
+    }
+
+    #[test]
+    fn reject_invalid_expressions() {
+        for text in &["", "MIT OR", "(MIT", "MIT)", "MIT AND AND BSD", "MIT WITH",
+                      "LicenseRef-foo+", "LicenseRef-", "MIT/X11", "MIT BSD"] {
+            assert!(Expression::parse(text).is_err(), "{} parsed", text);
+        }
+    }
+
+    #[test]
+    fn find_tags_in_comments() {
+        let text = ["// SPDX-License-Identifier: MIT",
+                    "//! SPDX-License-Identifier: Apache-2.0",
+                    "/// SPDX-License-Identifier: MIT OR Apache-2.0",
+                    "/*! SPDX-License-Identifier: BSD-3-Clause */",
+                    "/* SPDX-License-Identifier: GPL-2.0-only */",
+                    "# SPDX-License-Identifier: ISC",
+                    "<!-- SPDX-License-Identifier: CC0-1.0 -->",
+                    "SPDX-License-Identifier: 0BSD",
+                    "let x = \"SPDX-License-Identifier: MIT\";",
+                    "-- SPDX-License-Identifier:"]
+                       .join("\n");
+        let tags = find_tags(&text);
+        let values: Vec<&str> = tags.iter().map(|x| x.1.as_str()).collect();
+        assert_eq!(values,
+                   vec!["MIT", "Apache-2.0", "MIT OR Apache-2.0", "BSD-3-Clause",
+                        "GPL-2.0-only", "ISC", "CC0-1.0", "0BSD"]);
+        assert_eq!(tags[3].0, 4);
+    }
+}
@@ -0,0 +1,277 @@
+#![allow(non_snake_case)]
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rustc_serialize::json;
+
+use corpus::LicenseSource;
+use spdx::template;
+use error::{LiceumError, Result};
+use util::read_file;
+
+/// Prefix of deprecated licenses in the `text` directory.
+static DEPRECATED_PREFIX: &'static str = "deprecated_";
+
+/// SPDX metadata of a license stored alongside its corpus.
+#[derive(RustcDecodable, RustcEncodable, Debug, Clone, PartialEq, Eq)]
+pub struct LicenseMeta {
+    /// SPDX license identifier
+    pub id: String,
+    /// Full name of the license
+    pub name: String,
+    pub deprecated: bool,
+    pub osi_approved: bool,
+    pub fsf_libre: bool,
+    /// Header the license recommends to put into source files
+    pub standard_header: Option<String>,
+}
+
+/// License entry of `json/details/<id>.json`
+#[derive(RustcDecodable)]
+struct Details {
+    licenseId: String,
+    name: String,
+    licenseText: String,
+    standardLicenseTemplate: Option<String>,
+    standardLicenseHeader: Option<String>,
+    isDeprecatedLicenseId: Option<bool>,
+    isOsiApproved: Option<bool>,
+    isFsfLibre: Option<bool>,
+}
+
+/// License entry of `json/licenses.json`
+#[derive(RustcDecodable)]
+struct Summary {
+    licenseId: String,
+    name: String,
+    isDeprecatedLicenseId: Option<bool>,
+    isOsiApproved: Option<bool>,
+    isFsfLibre: Option<bool>,
+}
+
+#[derive(RustcDecodable)]
+struct Licenses {
+    licenses: Vec<Summary>,
+}
+
+impl LicenseMeta {
+    fn from_summary(s: &Summary) -> LicenseMeta {
+        LicenseMeta {
+            id: s.licenseId.clone(),
+            name: s.name.clone(),
+            deprecated: s.isDeprecatedLicenseId.unwrap_or(false),
+            osi_approved: s.isOsiApproved.unwrap_or(false),
+            fsf_libre: s.isFsfLibre.unwrap_or(false),
+            standard_header: None,
+        }
+    }
+}
+
+/// Files in `dir` with extension `ext`, ordered by name.
+fn files_with_extension(dir: &Path, ext: &str) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for entry in try!(fs::read_dir(dir)) {
+        let p = try!(entry).path();
+        if p.is_file() && p.extension().map_or(false, |x| x == ext) {
+            files.push(p);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn path_str(p: &Path) -> Result<&str> {
+    p.to_str().ok_or_else(|| LiceumError::Encoding(format!("{:?}", p)))
+}
+
+/// Read licenses from the `json/details` directory.
+fn read_details(dir: &Path) -> Result<Vec<LicenseSource>> {
+    let mut sources: Vec<LicenseSource> = Vec::new();
+    for p in try!(files_with_extension(dir, "json")) {
+        let d: Details = try!(json::decode(&try!(read_file(try!(path_str(&p))))));
+        sources.push(LicenseSource {
+            name: d.licenseId.clone(),
+            text: d.licenseText,
+            template: d.standardLicenseTemplate,
+            meta: Some(LicenseMeta {
+                id: d.licenseId,
+                name: d.name,
+                deprecated: d.isDeprecatedLicenseId.unwrap_or(false),
+                osi_approved: d.isOsiApproved.unwrap_or(false),
+                fsf_libre: d.isFsfLibre.unwrap_or(false),
+                standard_header: d.standardLicenseHeader.and_then(|x| {
+                    if x.trim().is_empty() {
+                        None
+                    } else {
+                        Some(x)
+                    }
+                }),
+            }),
+        });
+    }
+    Ok(sources)
+}
+
+/// Read licenses from the `text` or `template` directory, metadata are taken from
+/// `licenses` (`json/licenses.json`) if it exists.
+fn read_text(dir: &Path, licenses: &Path) -> Result<Vec<LicenseSource>> {
+    let summaries = if licenses.is_file() {
+        let l: Licenses = try!(json::decode(&try!(read_file(try!(path_str(licenses))))));
+        l.licenses
+    } else {
+        Vec::new()
+    };
+
+    let mut sources: Vec<LicenseSource> = Vec::new();
+    for p in try!(files_with_extension(dir, "txt")) {
+        let stem = p.file_stem().map_or(String::new(), |x| x.to_string_lossy().into_owned());
+        let stem = String::from(stem.trim_right_matches(template::TEMPLATE_SUFFIX));
+        let deprecated = stem.starts_with(DEPRECATED_PREFIX);
+        let id = if deprecated {
+            String::from(&stem[DEPRECATED_PREFIX.len()..])
+        } else {
+            stem
+        };
+
+        let meta = summaries.iter().find(|x| x.licenseId == id).map(|s| {
+            let mut m = LicenseMeta::from_summary(s);
+            m.deprecated |= deprecated;
+            m
+        });
+        let (text, template) = template::split(try!(read_file(try!(path_str(&p)))));
+        sources.push(LicenseSource {
+            name: id,
+            text: text,
+            template: template,
+            meta: meta,
+        });
+    }
+    Ok(sources)
+}
+
+/// Read licenses from a checkout of the SPDX license-list-data
+/// repository. `dir` is either the checkout itself, its `json/details`,
+/// `text` or `template` directory, `None` is returned for any other
+/// directory.
+pub fn read(dir: &str) -> Result<Option<Vec<LicenseSource>>> {
+    let dir = Path::new(dir);
+    let name = dir.file_name().map_or(String::new(), |x| x.to_string_lossy().into_owned());
+    let parent = dir.parent().unwrap_or(dir);
+
+    if dir.join("json").join("details").is_dir() {
+        return read_details(&dir.join("json").join("details")).map(Some);
+    }
+    if name == "details" && parent.file_name().map_or(false, |x| x == "json") {
+        return read_details(dir).map(Some);
+    }
+    if (name == "text" || name == "template") && parent.join("json").is_dir() {
+        return read_text(dir, &parent.join("json").join("licenses.json")).map(Some);
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::{env, fs, process};
+
+    use util::write_file;
+    use super::read;
+
+    static DETAILS: &'static str = r#"{"licenseId": "MIT", "name": "MIT License",
+        "licenseText": "Permission is hereby granted", "standardLicenseHeader": " ",
+        "standardLicenseTemplate":
+            "Permission is <<var;name=\"who\";original=\"hereby\";match=\".+\">> granted",
+        "isOsiApproved": true, "isFsfLibre": true}"#;
+
+    static LICENSES: &'static str = r#"{"licenses": [
+        {"licenseId": "MIT", "name": "MIT License", "isOsiApproved": true},
+        {"licenseId": "GPL-2.0", "name": "GNU General Public License v2.0 only",
+         "isDeprecatedLicenseId": false, "isFsfLibre": true}]}"#;
+
+    static TEMPLATE: &'static str = "Copyright <<var;name=\"copyright\";original=\"(c) Jane\";\
+                                     match=\".+\">> granted";
+
+    /// Checkout of the SPDX license list `case` removed when dropped.
+    struct Checkout(PathBuf);
+
+    impl Checkout {
+        fn new(case: &str) -> Checkout {
+            let root = env::temp_dir().join(format!("liceum-list-{}-{}", process::id(), case));
+            for dir in &["json/details", "text", "template", "other"] {
+                fs::create_dir_all(root.join(dir)).unwrap();
+            }
+            let files = [("json/details/MIT.json", DETAILS),
+                         ("json/licenses.json", LICENSES),
+                         ("text/MIT.txt", "Permission is hereby granted"),
+                         ("text/deprecated_GPL-2.0.txt", "GNU General Public License"),
+                         ("text/README.md", "Not a license"),
+                         ("template/MIT.template.txt", TEMPLATE)];
+            for &(name, text) in &files {
+                write_file(&root.join(name).to_string_lossy(), text).unwrap();
+            }
+            Checkout(root)
+        }
+
+        fn path(&self, dir: &str) -> String {
+            self.0.join(dir).to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for Checkout {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn details_layout() {
+        let checkout = Checkout::new("details");
+        for dir in &["", "json/details"] {
+            let sources = read(&checkout.path(dir)).unwrap().unwrap();
+            assert_eq!(sources.len(), 1);
+            let mit = &sources[0];
+            assert_eq!((&mit.name[..], &mit.text[..]), ("MIT", "Permission is hereby granted"));
+            assert!(mit.template.as_ref().unwrap().contains("<<var;name=\"who\""));
+            let meta = mit.meta.as_ref().unwrap();
+            assert_eq!((&meta.id[..], &meta.name[..]), ("MIT", "MIT License"));
+            assert_eq!((meta.deprecated, meta.osi_approved, meta.fsf_libre),
+                       (false, true, true));
+            assert_eq!(meta.standard_header, None);
+        }
+    }
+
+    #[test]
+    fn text_layout() {
+        let checkout = Checkout::new("text");
+        let sources = read(&checkout.path("text")).unwrap().unwrap();
+        let names: Vec<&str> = sources.iter().map(|x| &x.name[..]).collect();
+        assert_eq!(names, ["MIT", "GPL-2.0"]);
+        assert!(sources.iter().all(|x| x.template.is_none()));
+
+        let mit = sources[0].meta.as_ref().unwrap();
+        assert_eq!((mit.deprecated, mit.osi_approved, mit.fsf_libre), (false, true, false));
+        let gpl = sources[1].meta.as_ref().unwrap();
+        assert_eq!(gpl.name, "GNU General Public License v2.0 only");
+        assert_eq!((gpl.deprecated, gpl.osi_approved, gpl.fsf_libre), (true, false, true));
+    }
+
+    #[test]
+    fn template_layout() {
+        let checkout = Checkout::new("template");
+        let sources = read(&checkout.path("template")).unwrap().unwrap();
+        assert_eq!(sources.len(), 1);
+        let mit = &sources[0];
+        assert_eq!((&mit.name[..], &mit.text[..]), ("MIT", "Copyright (c) Jane granted"));
+        assert_eq!(mit.template.as_ref().map(|x| &x[..]), Some(TEMPLATE));
+        assert_eq!(mit.meta.as_ref().map(|x| &x.name[..]), Some("MIT License"));
+    }
+
+    #[test]
+    fn ignore_other_directories() {
+        let checkout = Checkout::new("other");
+        assert!(read(&checkout.path("other")).unwrap().is_none());
+        assert!(read(&checkout.path("json")).unwrap().is_none());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod expression;
+pub mod list;
+pub mod template;
@@ -0,0 +1,420 @@
+use regex::Regex;
+
+/// Suffix of the file stems in the `template` directory of the SPDX
+/// license-list-data.
+pub static TEMPLATE_SUFFIX: &'static str = ".template";
+
+/// Number of words of the required text around a variable used to locate
+/// it in a scanned text.
+const CONTEXT_WORDS: usize = 4;
+
+/// Maximal length of a variable located by `VarCheck`.
+const VAR_LENGTH: usize = 500;
+
+/// Replaceable field of a license template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Var {
+    pub name: String,
+    /// Text of the field in the original license
+    pub original: String,
+    /// Regular expression the text of the field has to match
+    pub pattern: String,
+}
+
+/// Part of a license template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Part {
+    /// Text which has to be present
+    Text(String),
+    /// Replaceable text, `<<var;...>>`
+    Var(Var),
+    /// Text which may be omitted, `<<beginOptional>>...<<endOptional>>`
+    Optional(Vec<Part>),
+}
+
+/// License template following the SPDX license template markup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    pub parts: Vec<Part>,
+}
+
+/// Variable of a license template together with the required words
+/// around it, stored in the corpus to validate matches.
+#[derive(RustcDecodable, RustcEncodable, Debug, Clone, PartialEq, Eq)]
+pub struct VarContext {
+    pub name: String,
+    /// Regular expression the text of the variable has to match
+    pub pattern: String,
+    /// Last words of the required text preceding the variable
+    pub before: Vec<String>,
+    /// First words of the required text following the variable
+    pub after: Vec<String>,
+}
+
+/// Compiled `VarContext` checking the variable in scanned texts.
+#[derive(Debug, Clone)]
+pub struct VarCheck {
+    pub name: String,
+    before: Option<Regex>,
+    after: Option<Regex>,
+    // the words around with the variable pattern in between
+    full: Regex,
+}
+
+/// Predicate determining whether `text` contains SPDX template markup.
+pub fn is_template(text: &str) -> bool {
+    text.contains("<<var;") || text.contains("<<beginOptional")
+}
+
+// Patterns matching any text, `.*`, `.+` and `.{m,n}`, compiled once per
+// thread
+thread_local!(static ANY: Regex = Regex::new(r"^\.(?:\*|\+|\{[0-9]*,[0-9]*\})$").unwrap());
+
+/// Predicate determining whether `pattern` matches any text, such
+/// variables can't rule a match out.
+fn accepts_anything(pattern: &str) -> bool {
+    ANY.with(|any| any.is_match(pattern.trim()))
+}
+
+/// Alphanumeric words of `text`.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Plain text and template of `text`, the template is `None` unless `text`
+/// contains valid template markup.
+pub fn split(text: String) -> (String, Option<String>) {
+    if !is_template(&text) {
+        return (text, None);
+    }
+    match Template::parse(&text) {
+        Ok(t) => (t.original(), Some(text)),
+        Err(_) => (text, None),
+    }
+}
+
+/// Find the end of the markup starting at `start`, `>>` inside of quoted
+/// values doesn't end it.
+fn markup_end(text: &str, start: usize) -> Option<usize> {
+    let mut quoted = false;
+    let mut prev = '\0';
+    for (i, c) in text[start..].char_indices() {
+        if c == '"' && prev != '\\' {
+            quoted = !quoted;
+        } else if !quoted && c == '>' && prev == '>' {
+            return Some(start + i - 1);
+        }
+        prev = c;
+    }
+    None
+}
+
+/// Value of `key="value"` in the markup `fields`.
+fn field(fields: &[&str], key: &str) -> String {
+    let prefix = format!("{}=", key);
+    fields.iter()
+          .map(|x| x.trim())
+          .find(|x| x.starts_with(&prefix))
+          .map_or(String::new(), |x| {
+              x[prefix.len()..].trim_matches('"').replace("\\\"", "\"")
+          })
+}
+
+/// Split markup `body` into fields, `;` inside of quoted values doesn't
+/// separate them.
+fn split_fields(body: &str) -> Vec<&str> {
+    let mut fields: Vec<&str> = Vec::new();
+    let (mut quoted, mut start, mut prev) = (false, 0, '\0');
+    for (i, c) in body.char_indices() {
+        if c == '"' && prev != '\\' {
+            quoted = !quoted;
+        } else if c == ';' && !quoted {
+            fields.push(&body[start..i]);
+            start = i + 1;
+        }
+        prev = c;
+    }
+    fields.push(&body[start..]);
+    fields
+}
+
+impl Template {
+    /// Parse `text` with SPDX template markup.
+    pub fn parse(text: &str) -> Result<Template, String> {
+        // Stack of optional blocks being parsed, the bottom is the template
+        let mut stack: Vec<Vec<Part>> = vec![Vec::new()];
+        let mut pos = 0;
+
+        while let Some(offset) = text[pos..].find("<<") {
+            let start = pos + offset;
+            if start > pos {
+                stack.last_mut().unwrap().push(Part::Text(String::from(&text[pos..start])));
+            }
+
+            let end = match markup_end(text, start + 2) {
+                Some(x) => x,
+                None => return Err(format!("Unterminated template markup at {}", start)),
+            };
+            let fields = split_fields(&text[start + 2..end]);
+            match fields[0].trim() {
+                "var" => {
+                    stack.last_mut().unwrap().push(Part::Var(Var {
+                        name: field(&fields, "name"),
+                        original: field(&fields, "original"),
+                        pattern: field(&fields, "match"),
+                    }))
+                }
+                "beginOptional" => stack.push(Vec::new()),
+                "endOptional" => {
+                    if stack.len() < 2 {
+                        return Err(format!("Unmatched endOptional at {}", start));
+                    }
+                    let optional = stack.pop().unwrap();
+                    stack.last_mut().unwrap().push(Part::Optional(optional));
+                }
+                x => return Err(format!("Unknown template markup: {}", x)),
+            }
+
+            pos = end + 2;
+        }
+
+        if pos < text.len() {
+            stack.last_mut().unwrap().push(Part::Text(String::from(&text[pos..])));
+        }
+        if stack.len() != 1 {
+            return Err(String::from("Unterminated beginOptional"));
+        }
+
+        Ok(Template { parts: stack.pop().unwrap() })
+    }
+
+    /// License text with variables replaced by their original text and
+    /// optional parts included.
+    pub fn original(&self) -> String {
+        let mut out = String::new();
+        append_original(&self.parts, &mut out);
+        out
+    }
+
+    /// Byte ranges of `original()` taken by the variables and optional
+    /// parts, empty variables give empty ranges.
+    pub fn variable_spans(&self) -> Vec<(usize, usize)> {
+        let mut spans: Vec<(usize, usize)> = Vec::new();
+        let mut out = String::new();
+        for (i, part) in self.parts.iter().enumerate() {
+            let start = out.len();
+            append_original(&self.parts[i..i + 1], &mut out);
+            match *part {
+                Part::Text(_) => {}
+                _ => spans.push((start, out.len())),
+            }
+        }
+        spans
+    }
+
+    /// Required text of the template, split at every variable and optional
+    /// part.
+    pub fn fixed_segments(&self) -> Vec<String> {
+        let mut segments: Vec<String> = vec![String::new()];
+        for part in &self.parts {
+            match *part {
+                Part::Text(ref x) => segments.last_mut().unwrap().push_str(x),
+                _ => segments.push(String::new()),
+            }
+        }
+        segments.retain(|x| !x.trim().is_empty());
+        segments
+    }
+
+    /// Required variables with a restrictive pattern together with the
+    /// required words around them.
+    pub fn var_contexts(&self) -> Vec<VarContext> {
+        let text = |i: Option<usize>| {
+            match i.and_then(|x| self.parts.get(x)) {
+                Some(&Part::Text(ref x)) => words(x),
+                _ => Vec::new(),
+            }
+        };
+
+        let mut contexts: Vec<VarContext> = Vec::new();
+        for (i, part) in self.parts.iter().enumerate() {
+            let var = match *part {
+                Part::Var(ref x) if !x.pattern.is_empty() && !accepts_anything(&x.pattern) => x,
+                _ => continue,
+            };
+
+            let mut before = text(i.checked_sub(1));
+            let skip = before.len().saturating_sub(CONTEXT_WORDS);
+            before.drain(..skip);
+            let mut after = text(Some(i + 1));
+            after.truncate(CONTEXT_WORDS);
+            if before.is_empty() && after.is_empty() {
+                continue;
+            }
+
+            contexts.push(VarContext {
+                name: var.name.clone(),
+                pattern: var.pattern.clone(),
+                before: before,
+                after: after,
+            });
+        }
+        contexts
+    }
+}
+
+impl VarContext {
+    /// Compile the check, `None` if the pattern isn't a valid regular
+    /// expression.
+    pub fn compile(&self) -> Option<VarCheck> {
+        let words = |x: &[String]| {
+            if x.is_empty() {
+                None
+            } else {
+                Regex::new(&format!("(?i){}", x.join(r"\W+"))).ok()
+            }
+        };
+        let full = format!(r"(?is){}\W*(?:{})\W*{}",
+                           self.before.join(r"\W+"),
+                           self.pattern,
+                           self.after.join(r"\W+"));
+
+        Regex::new(&full).ok().map(|full| {
+            VarCheck {
+                name: self.name.clone(),
+                before: words(&self.before),
+                after: words(&self.after),
+                full: full,
+            }
+        })
+    }
+}
+
+impl VarCheck {
+    /// Check the variable in `text`, `None` if the required words around it
+    /// aren't present.
+    pub fn check(&self, text: &str) -> Option<bool> {
+        let present = match (&self.before, &self.after) {
+            (&Some(ref b), &Some(ref a)) => {
+                b.find_iter(text).any(|(_, end)| {
+                    a.find(&text[end..]).map_or(false, |(start, _)| start <= VAR_LENGTH)
+                })
+            }
+            (&Some(ref x), &None) | (&None, &Some(ref x)) => x.is_match(text),
+            (&None, &None) => false,
+        };
+
+        if present {
+            Some(self.full.is_match(text))
+        } else {
+            None
+        }
+    }
+}
+
+fn append_original(parts: &[Part], out: &mut String) {
+    for part in parts {
+        match *part {
+            Part::Text(ref x) => out.push_str(x),
+            Part::Var(ref v) => out.push_str(&v.original),
+            Part::Optional(ref inner) => append_original(inner, out),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str, original: &str, pattern: &str) -> Part {
+        Part::Var(Var {
+            name: String::from(name),
+            original: String::from(original),
+            pattern: String::from(pattern),
+        })
+    }
+
+    fn text(x: &str) -> Part {
+        Part::Text(String::from(x))
+    }
+
+    static NESTED: &'static str = "Copyright <<var;name=\"copyright\";original=\"(c) 2016 \
+                                   Jane\";match=\".+\">>\nPermission is granted<<beginOptional>> \
+                                   free of charge<<beginOptional>>, to any \
+                                   person<<endOptional>><<endOptional>> to use it.";
+
+    static YEAR: &'static str = "Copyright (c) <<var;name=\"year\";original=\"2016\";\
+                                 match=\"[0-9]{4}\">> by the authors of the work, \
+                                 <<var;name=\"holder\";original=\"Jane\";match=\".+\">>.";
+
+    #[test]
+    fn parse_markup() {
+        let t = Template::parse(NESTED).unwrap();
+        assert_eq!(t.parts,
+                   vec![text("Copyright "),
+                        var("copyright", "(c) 2016 Jane", ".+"),
+                        text("\nPermission is granted"),
+                        Part::Optional(vec![text(" free of charge"),
+                                            Part::Optional(vec![text(", to any person")])]),
+                        text(" to use it.")]);
+        assert_eq!(t.original(),
+                   "Copyright (c) 2016 Jane\nPermission is granted free of charge, to any \
+                    person to use it.");
+    }
+
+    #[test]
+    fn quoted_markup_end() {
+        let t = Template::parse("a <<var;name=\"x\";original=\"b\";match=\"b>>|c\">> d").unwrap();
+        assert_eq!(t.parts, vec![text("a "), var("x", "b", "b>>|c"), text(" d")]);
+    }
+
+    #[test]
+    fn reject_malformed_markup() {
+        assert!(Template::parse("text<<endOptional>>").unwrap_err().contains("Unmatched"));
+        assert!(Template::parse("text <<var;name=\"x\"").unwrap_err().contains("Unterminated"));
+        assert!(Template::parse("<<beginOptional>>text").is_err());
+        assert!(Template::parse("<<bogus>>").is_err());
+    }
+
+    #[test]
+    fn fixed_and_variable_text() {
+        let t = Template::parse(NESTED).unwrap();
+        assert_eq!(t.fixed_segments(),
+                   vec!["Copyright ", "\nPermission is granted", " to use it."]);
+
+        let original = t.original();
+        let spans: Vec<&str> = t.variable_spans()
+                                .iter()
+                                .map(|&(start, end)| &original[start..end])
+                                .collect();
+        assert_eq!(spans, vec!["(c) 2016 Jane", " free of charge, to any person"]);
+    }
+
+    #[test]
+    fn contexts_of_restrictive_vars() {
+        let contexts = Template::parse(YEAR).unwrap().var_contexts();
+        assert_eq!(contexts,
+                   vec![VarContext {
+                            name: String::from("year"),
+                            pattern: String::from("[0-9]{4}"),
+                            before: vec![String::from("Copyright"), String::from("c")],
+                            after: ["by", "the", "authors", "of"]
+                                       .iter()
+                                       .map(|x| String::from(*x))
+                                       .collect(),
+                        }]);
+    }
+
+    #[test]
+    fn check_vars() {
+        let check = Template::parse(YEAR).unwrap().var_contexts()[0].compile().unwrap();
+        assert_eq!(check.check("Copyright (c) 2019 by the authors of the work"),
+                   Some(true));
+        assert_eq!(check.check("Copyright (c) someone by the authors of the work"),
+                   Some(false));
+        assert_eq!(check.check("Permission is granted to use it"), None);
+    }
+}
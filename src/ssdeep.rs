@@ -1,6 +1,27 @@
-use std::process::Command;
-use csv::Reader;
+use std::fs::File;
+use std::io::{Read, Error};
+use std::cmp;
+use std::fmt;
 use std::path::Path;
+use csv::Reader;
+use walkdir::WalkDir;
+
+/// Size of the rolling hash window.
+const ROLLING_WINDOW: usize = 7;
+/// Smallest block size used when triggering block boundaries.
+const MIN_BLOCKSIZE: u32 = 3;
+/// Maximum length of the first part of the hash.
+const SPAMSUM_LENGTH: usize = 64;
+/// Number of block sizes tracked while hashing a single input.
+const NUM_BLOCKHASHES: usize = 31;
+/// FNV prime and offset basis used by the block hashes.
+const HASH_PRIME: u32 = 0x01000193;
+const HASH_INIT: u32 = 0x28021967;
+
+static B64: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Header line `ssdeep` emits in front of the hash list.
+static HASHES_HEADER: &'static str = "ssdeep,1.1--blocksize:hash:hash,filename";
 
 /// Holds result of ssdeep comparison.
 #[derive(Debug)]
@@ -10,40 +31,463 @@ pub struct CompareResult {
     pub file_b: String,
 }
 
+/// Parsed context triggered piecewise hash in the `blocksize:hash:hash` form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyHash {
+    pub block_size: u32,
+    pub first: String,
+    pub second: String,
+}
+
+impl FuzzyHash {
+    /// Parse `hash` from its textual representation, returns `None` if the
+    /// string is not a well formed ssdeep hash.
+    pub fn parse(hash: &str) -> Option<FuzzyHash> {
+        let mut parts = hash.trim().splitn(3, ':');
+        let block_size = match parts.next().and_then(|x| x.parse::<u32>().ok()) {
+            Some(x) => x,
+            None => return None,
+        };
+        let first = match parts.next() {
+            Some(x) => x,
+            None => return None,
+        };
+        // the second part may be followed by `,"filename"`
+        let second = match parts.next() {
+            Some(x) => x.split(',').next().unwrap_or(""),
+            None => return None,
+        };
+
+        Some(FuzzyHash {
+            block_size: block_size,
+            first: String::from(first),
+            second: String::from(second),
+        })
+    }
+}
+
+impl fmt::Display for FuzzyHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.block_size, self.first, self.second)
+    }
+}
+
+/// Rolling hash over the last `ROLLING_WINDOW` bytes of input.
+struct Roll {
+    window: [u8; ROLLING_WINDOW],
+    h1: u32,
+    h2: u32,
+    h3: u32,
+    n: usize,
+}
+
+impl Roll {
+    fn new() -> Roll {
+        Roll {
+            window: [0; ROLLING_WINDOW],
+            h1: 0,
+            h2: 0,
+            h3: 0,
+            n: 0,
+        }
+    }
+
+    fn update(&mut self, c: u8) {
+        let c = c as u32;
+        self.h2 = self.h2.wrapping_sub(self.h1).wrapping_add(ROLLING_WINDOW as u32 * c);
+        self.h1 = self.h1.wrapping_add(c).wrapping_sub(self.window[self.n] as u32);
+        self.window[self.n] = c as u8;
+        self.n = (self.n + 1) % ROLLING_WINDOW;
+        self.h3 = (self.h3 << 5) ^ c;
+    }
+
+    fn sum(&self) -> u32 {
+        self.h1.wrapping_add(self.h2).wrapping_add(self.h3)
+    }
+}
+
+/// Piecewise hash state for a single block size.
+#[derive(Clone)]
+struct BlockHash {
+    digest: Vec<u8>,
+    // character stored at the last position once the digest is full
+    last: Option<u8>,
+    half: Option<u8>,
+    h: u32,
+    halfh: u32,
+}
+
+impl BlockHash {
+    fn new() -> BlockHash {
+        BlockHash {
+            digest: Vec::with_capacity(SPAMSUM_LENGTH),
+            last: None,
+            half: None,
+            h: HASH_INIT,
+            halfh: HASH_INIT,
+        }
+    }
+}
+
+#[inline]
+fn sum_hash(c: u8, h: u32) -> u32 {
+    h.wrapping_mul(HASH_PRIME) ^ (c as u32)
+}
+
+#[inline]
+fn block_size(index: usize) -> u32 {
+    MIN_BLOCKSIZE << index
+}
+
+/// Compute the ssdeep (spamsum) hash of `data`.
+///
+/// Block hashes for all candidate block sizes are computed in a single pass
+/// and the final block size is picked the same way `ssdeep` does, so hashes
+/// are interchangeable with the ones produced by the reference tool.
+pub fn hash_bytes(data: &[u8]) -> String {
+    let mut roll = Roll::new();
+    let mut blocks = vec![BlockHash::new()];
+
+    for &c in data {
+        roll.update(c);
+        let h = roll.sum();
+
+        for b in &mut blocks {
+            b.h = sum_hash(c, b.h);
+            b.halfh = sum_hash(c, b.halfh);
+        }
+
+        let mut i = 0;
+        while i < blocks.len() {
+            let bs = block_size(i);
+            if h % bs != bs - 1 {
+                break;
+            }
+
+            // The first trigger of the largest block size forks a new one
+            if blocks[i].digest.is_empty() && blocks.len() < NUM_BLOCKHASHES {
+                let mut next = BlockHash::new();
+                next.h = blocks[i].h;
+                next.halfh = blocks[i].halfh;
+                blocks.push(next);
+            }
+
+            let b = &mut blocks[i];
+            b.last = Some(B64[(b.h % 64) as usize]);
+            b.half = Some(B64[(b.halfh % 64) as usize]);
+            if b.digest.len() < SPAMSUM_LENGTH - 1 {
+                b.digest.push(B64[(b.h % 64) as usize]);
+                b.last = None;
+                b.h = HASH_INIT;
+                if b.digest.len() < SPAMSUM_LENGTH / 2 {
+                    b.halfh = HASH_INIT;
+                    b.half = None;
+                }
+            }
+
+            i += 1;
+        }
+    }
+
+    let h = roll.sum();
+    let total = data.len();
+
+    // Pick the block size so that the first hash is between 32 and 64 chars
+    let mut bi = 0;
+    while (block_size(bi) as usize) * SPAMSUM_LENGTH < total {
+        bi += 1;
+    }
+    while bi >= blocks.len() {
+        bi -= 1;
+    }
+    while bi > 0 && blocks[bi].digest.len() < SPAMSUM_LENGTH / 2 {
+        bi -= 1;
+    }
+
+    let mut first = blocks[bi].digest.clone();
+    if h != 0 {
+        first.push(B64[(blocks[bi].h % 64) as usize]);
+    } else if let Some(c) = blocks[bi].last {
+        first.push(c);
+    }
+
+    let mut second: Vec<u8> = Vec::new();
+    if bi + 1 < blocks.len() {
+        let b = &blocks[bi + 1];
+        let len = cmp::min(b.digest.len(), SPAMSUM_LENGTH / 2 - 1);
+        second.extend_from_slice(&b.digest[..len]);
+        if h != 0 {
+            second.push(B64[(b.halfh % 64) as usize]);
+        } else if let Some(c) = b.half {
+            second.push(c);
+        }
+    } else if h != 0 {
+        second.push(B64[(blocks[bi].h % 64) as usize]);
+    }
+
+    format!("{}:{}:{}",
+            block_size(bi),
+            String::from_utf8_lossy(&first),
+            String::from_utf8_lossy(&second))
+}
+
+/// Compute the ssdeep hash of the contents of `file`.
+pub fn hash_file(file: &str) -> Result<String, Error> {
+    let mut f = try!(File::open(file));
+    let mut data = Vec::new();
+    try!(f.read_to_end(&mut data));
+    Ok(hash_bytes(&data))
+}
+
+/// Collapse runs of more than three identical characters into three,
+/// such runs carry little information and skew the edit distance.
+fn eliminate_sequences(s: &str) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(s.len());
+    for &c in s.as_bytes() {
+        let len = out.len();
+        if len >= 3 && out[len - 1] == c && out[len - 2] == c && out[len - 3] == c {
+            continue;
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Check whether `a` and `b` share a substring of at least `ROLLING_WINDOW`
+/// characters.
+fn has_common_substring(a: &[u8], b: &[u8]) -> bool {
+    if a.len() < ROLLING_WINDOW || b.len() < ROLLING_WINDOW {
+        return false;
+    }
+
+    a.windows(ROLLING_WINDOW).any(|w| b.windows(ROLLING_WINDOW).any(|x| x == w))
+}
+
+/// Weighted edit distance, insertion and deletion cost 1 and substitution 2.
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut prev: Vec<usize> = (0..b.len() + 1).collect();
+    let mut cur: Vec<usize> = vec![0; b.len() + 1];
+
+    for i in 0..a.len() {
+        cur[0] = i + 1;
+        for j in 0..b.len() {
+            let cost = if a[i] == b[j] { 0 } else { 2 };
+            cur[j + 1] = cmp::min(cmp::min(prev[j + 1] + 1, cur[j] + 1), prev[j] + cost);
+        }
+        ::std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()]
+}
+
+/// Score two hash parts computed with the same `block_size` on a 0-100 scale.
+fn score_strings(a: &[u8], b: &[u8], block_size: u32) -> u32 {
+    if a.len() > SPAMSUM_LENGTH || b.len() > SPAMSUM_LENGTH {
+        return 0;
+    }
+
+    if !has_common_substring(a, b) {
+        return 0;
+    }
+
+    let mut score = edit_distance(a, b) as u32;
+    score = (score * SPAMSUM_LENGTH as u32) / (a.len() + b.len()) as u32;
+    score = (100 * score) / SPAMSUM_LENGTH as u32;
+    if score >= 100 {
+        return 0;
+    }
+    score = 100 - score;
+
+    // Small block sizes can't claim high similarity on short hashes
+    let window = ROLLING_WINDOW as u32;
+    if block_size >= (99 + window) / window * MIN_BLOCKSIZE {
+        return score;
+    }
+
+    let cap = block_size / MIN_BLOCKSIZE * cmp::min(a.len(), b.len()) as u32;
+    cmp::min(score, cap)
+}
+
+/// Compare two ssdeep hashes and return their similarity in the 0-100 range.
+/// Malformed hashes and hashes with incompatible block sizes score 0.
+pub fn similarity(hash_a: &str, hash_b: &str) -> u32 {
+    let (a, b) = match (FuzzyHash::parse(hash_a), FuzzyHash::parse(hash_b)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return 0,
+    };
+
+    let (bs_a, bs_b) = (a.block_size, b.block_size);
+    if bs_a != bs_b && bs_a != bs_b * 2 && bs_b != bs_a * 2 {
+        return 0;
+    }
+
+    let (a1, a2) = (eliminate_sequences(&a.first), eliminate_sequences(&a.second));
+    let (b1, b2) = (eliminate_sequences(&b.first), eliminate_sequences(&b.second));
+
+    if bs_a == bs_b && a1 == b1 {
+        return 100;
+    }
+
+    if bs_a == bs_b {
+        cmp::max(score_strings(&a1, &b1, bs_a),
+                 score_strings(&a2, &b2, bs_a * 2))
+    } else if bs_a == bs_b * 2 {
+        score_strings(&a1, &b2, bs_a)
+    } else {
+        score_strings(&a2, &b1, bs_b)
+    }
+}
+
+/// Load `hash, filename` pairs from ssdeep hashes `data`.
+pub fn parse_hashes(data: &str) -> Vec<(String, String)> {
+    let mut reader = Reader::from_string(data).has_headers(false).flexible(true);
+    let mut hashes: Vec<(String, String)> = Vec::new();
+    for line in reader.records() {
+        let record = match line {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+
+        if record.len() < 2 || record[0].starts_with("ssdeep,") || record[0] == "ssdeep" {
+            continue;
+        }
+
+        hashes.push((record[0].clone(), record[1].clone()));
+    }
+
+    hashes
+}
+
+/// Format `hash, filename` pairs into the ssdeep hashes file format.
+pub fn format_hashes(hashes: &[(String, String)]) -> String {
+    let mut out = String::from(HASHES_HEADER);
+    out.push('\n');
+
+    for &(ref hash, ref name) in hashes {
+        out.push_str(&format!("{},\"{}\"\n", hash, name));
+    }
+
+    out
+}
+
 /// Compute ssdeep hash, recursively, for all files in `dir` directory
-/// and return a single string with one hash per line.
-pub fn compute_directory(dir: &str) -> String {
-    match Command::new("ssdeep").arg("-br").arg(dir).output() {
-        Ok(output) => String::from_utf8_lossy(&output.stdout).into_owned(),
-        Err(_) => String::new(), 
+/// and return `hash, filename` pairs.
+pub fn hash_directory(dir: &str) -> Vec<(String, String)> {
+    let mut hashes: Vec<(String, String)> = Vec::new();
+
+    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let path = match entry.path().to_str() {
+            Some(x) => x,
+            None => continue,
+        };
+        if let Ok(hash) = hash_file(path) {
+            hashes.push((hash, entry.file_name().to_string_lossy().into_owned()));
+        }
     }
+
+    hashes
+}
+
+/// Compute ssdeep hash, recursively, for all files in `dir` directory
+/// and return a single string with one hash per line, in the same format
+/// as `ssdeep -br` would.
+pub fn compute_directory(dir: &str) -> String {
+    format_hashes(&hash_directory(dir))
 }
 
 /// Compare ssdeep hashes from `data_file`, recursively, against all files
 /// in `dir` directory and return only those above `threshold` similarity.
 pub fn compare(data_file: &str, dir: &str, threshold: u32) -> Vec<CompareResult> {
-    let result = match Command::new("ssdeep").arg("-rcm").arg(data_file).arg(dir).output() {
-        Ok(output) => String::from_utf8_lossy(&output.stdout).into_owned(),
-        Err(_) => String::new(), 
-    };
+    let mut data = String::new();
+    if let Ok(mut f) = File::open(data_file) {
+        f.read_to_string(&mut data).ok();
+    }
 
-    let mut reader = Reader::from_string(result).has_headers(false);
+    let known = parse_hashes(&data);
     let mut res: Vec<CompareResult> = Vec::new();
-    for line in reader.decode() {
-        let (src, template, score): (String, String, u32) = line.unwrap();
+    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let src = match entry.path().to_str() {
+            Some(x) => x,
+            None => continue,
+        };
+        let hash = match hash_file(src) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
 
-        if score > threshold {
-            res.push(CompareResult {
-                similarity: score,
-                file_a: src,
-                file_b: String::from(Path::new(&template)
-                                         .file_stem()
-                                         .unwrap()
-                                         .to_str()
-                                         .unwrap()),
-            });
+        for &(ref template_hash, ref template) in &known {
+            // Hashes listed without a file name can't be reported
+            let name = match Path::new(template).file_stem() {
+                Some(x) => x.to_string_lossy().into_owned(),
+                None => continue,
+            };
+            let score = similarity(&hash, template_hash);
+            if score > threshold {
+                res.push(CompareResult {
+                    similarity: score,
+                    file_a: String::from(src),
+                    file_b: name,
+                });
+            }
         }
     }
 
     res
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_matches_reference() {
+        assert_eq!(hash_bytes(b""), "3::");
+        assert_eq!(hash_bytes(b"Hello there!"), "3:aNRn:aNRn");
+    }
+
+    #[test]
+    fn similarity_matches_reference() {
+        assert_eq!(similarity("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+                              "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx"),
+                   22);
+    }
+
+    #[test]
+    fn similarity_of_block_sizes() {
+        let h = concat!("96:O9R/J/ZLkrihjP1rPFpzUF1KPDzbVP7nPkj/kRXt3N5A3kdVd1/vmTf1eF1Pltva:",
+                        "miihdfrzbpnCQ3c49818w1");
+        assert_eq!(similarity(h, h), 100);
+        assert_eq!(similarity(h, "384:miihdfrzbpnCQ3c49818w1:x"), 0);
+        assert_eq!(similarity(h, "not a hash"), 0);
+    }
+
+    #[test]
+    fn parse_hash() {
+        let h = FuzzyHash::parse("3:aNRn:aNRn,\"hello.txt\"").unwrap();
+        assert_eq!(h.block_size, 3);
+        assert_eq!(h.first, "aNRn");
+        assert_eq!(h.second, "aNRn");
+        assert_eq!(h.to_string(), "3:aNRn:aNRn");
+        assert_eq!(FuzzyHash::parse("x:aNRn:aNRn"), None);
+        assert_eq!(FuzzyHash::parse("3:aNRn"), None);
+    }
+
+    #[test]
+    fn eliminate_long_sequences() {
+        assert_eq!(eliminate_sequences("aaaaabccccd"), b"aaabcccd".to_vec());
+    }
+
+    #[test]
+    fn hashes_file_round_trip() {
+        let hashes = vec![(String::from("3:aNRn:aNRn"), String::from("MIT"))];
+        assert_eq!(parse_hashes(&format_hashes(&hashes)), hashes);
+    }
+}
@@ -0,0 +1,57 @@
+use std::fs::File;
+use std::io::{Read, Error, Write};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::collections::hash_map::Entry::{Occupied, Vacant};
+
+use sha1::Sha1;
+
+use error::Result;
+
+pub type IoResult<T> = ::std::result::Result<T, Error>;
+
+/// Insert multiple values under a single key
+pub trait OneToMany<K, V> {
+    fn insert_one(&mut self, key: K, value: V);
+}
+
+impl<K: Eq + Hash, V> OneToMany<K, V> for HashMap<K, Vec<V>> {
+    /// If `key` is not present insert the key with a vector containing the `value`
+    /// whereas if `key` is already present then push the `value` into the vector
+    fn insert_one(&mut self, key: K, value: V) {
+        match self.entry(key) {
+            Vacant(entry) => {
+                entry.insert(vec![value]);
+            }
+            Occupied(mut entry) => entry.get_mut().push(value),
+        };
+    }
+}
+
+/// Read the contents of `file`.
+pub fn read_bytes(file: &str) -> Result<Vec<u8>> {
+    let mut f = try!(File::open(file));
+    let mut data = Vec::new();
+    try!(f.read_to_end(&mut data));
+    Ok(data)
+}
+
+/// Read `file` into a string, files which are not valid UTF-8 are
+/// reported as `LiceumError::Encoding`.
+pub fn read_file(file: &str) -> Result<String> {
+    Ok(try!(String::from_utf8(try!(read_bytes(file)))))
+}
+
+/// Hex encoded SHA1 digest of `data`.
+pub fn sha1_hex(data: &[u8]) -> String {
+    let mut m = Sha1::new();
+    m.update(data);
+    m.digest().to_string()
+}
+
+pub fn write_file(file: &str, data: &str) -> IoResult<usize> {
+    let mut f = try!(File::create(file));
+    let written = try!(f.write(data.as_bytes()));
+    // println!(" {} bytes written", written);
+    Ok(written)
+}